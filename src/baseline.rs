@@ -0,0 +1,361 @@
+// Baseline checks the values applied to the account against the minimum values declared in the
+// config file, and files increase requests for the quotas that have drifted below them.
+
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+use crate::catalog::QuotaDefinition;
+use crate::config::BaselineRule;
+use crate::quotas::{self, GLOBAL_REGION};
+use crate::scan::{self, ScanOptions};
+use crate::scheduler::Scheduler;
+use crate::services::servicequota;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Status {
+    // the applied value is below the baseline
+    Below,
+    // the applied value is below the baseline, but an increase to the value is already open
+    Requested(f64),
+    // the applied value meets the baseline
+    Compliant,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Below => write!(f, "below baseline"),
+            Self::Requested(value) => write!(f, "increase to {} requested", value),
+            Self::Compliant => write!(f, "compliant"),
+        }
+    }
+}
+
+// Drift is the applied value of a quota against its baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    // the region the quota was listed in, global quotas are listed in the canonical region
+    pub region: String,
+    pub global: bool,
+    pub account_id: String,
+    pub service_code: String,
+    pub quota_code: String,
+    pub name: String,
+    pub applied: f64,
+    pub minimum: f64,
+    pub status: Status,
+}
+
+impl Drift {
+    // display_region is the region reported for the quota, "global" for global quotas
+    pub fn display_region(&self) -> &str {
+        match self.global {
+            true => GLOBAL_REGION,
+            false => &self.region,
+        }
+    }
+}
+
+// minimum is the highest minimum of the rules matching the quota, so that overlapping rules
+// never lower a baseline
+fn minimum(
+    rules: &[BaselineRule],
+    definition: &QuotaDefinition,
+    account_id: &str,
+    region: &str,
+) -> Option<f64> {
+    rules
+        .iter()
+        .filter(|rule| {
+            rule.matcher.matches_fields(
+                &definition.quota_code,
+                &definition.service_code,
+                account_id,
+                region,
+            )
+        })
+        .map(|rule| rule.minimum)
+        .reduce(f64::max)
+}
+
+// evaluate compares the applied value of the quota to its baseline. Quotas without a baseline
+// or an applied value are not evaluated.
+pub fn evaluate(
+    rules: &[BaselineRule],
+    region: &str,
+    definition: &QuotaDefinition,
+) -> Option<Drift> {
+    let account_id = quotas::parse_arn(&definition.arn).ok()?.account_id;
+    let display_region = match definition.global {
+        true => GLOBAL_REGION,
+        false => region,
+    };
+
+    let minimum = minimum(rules, definition, &account_id, display_region)?;
+    let applied = definition.applied_value?;
+
+    Some(Drift {
+        region: region.to_string(),
+        global: definition.global,
+        account_id,
+        service_code: definition.service_code.clone(),
+        quota_code: definition.quota_code.clone(),
+        name: definition.name.clone(),
+        applied,
+        minimum,
+        status: match applied < minimum {
+            true => Status::Below,
+            false => Status::Compliant,
+        },
+    })
+}
+
+// service_codes is the services the rules apply to, or None when a rule applies to quotas of any
+// service and every service must be listed
+fn service_codes(rules: &[BaselineRule]) -> Option<BTreeSet<String>> {
+    rules
+        .iter()
+        .map(|rule| rule.matcher.service_code.clone())
+        .collect()
+}
+
+// Baseline is the drift of every quota with a baseline, and the regions and services that could
+// not be checked
+#[derive(Debug, Default)]
+pub struct Baseline {
+    pub drifts: Vec<Drift>,
+    pub errors: Vec<String>,
+}
+
+// check compares the applied value of every quota with a baseline in each region. Quotas below
+// their baseline are checked for an open increase request.
+pub async fn check(
+    regions: &[String],
+    scheduler: &Arc<Scheduler>,
+    options: &ScanOptions,
+    rules: &[BaselineRule],
+) -> Baseline {
+    let mut result = Baseline::default();
+
+    for region in regions {
+        let client = servicequota::Client::new(region, Arc::clone(scheduler)).await;
+        let check_global = scan::global_region(regions) == Some(region.as_str());
+
+        // only the services of the rules are listed, unless a rule applies to any service
+        let service_codes = match (service_codes(rules), &options.filter.services) {
+            (Some(service_codes), _) => service_codes.into_iter().collect(),
+            (None, Some(services)) => services.clone(),
+            (None, None) => match client.service_codes().await {
+                Ok(service_codes) => service_codes,
+                Err(err) => {
+                    result.errors.push(format!(
+                        "failed to list services in region {}: {}",
+                        region, err
+                    ));
+                    continue;
+                }
+            },
+        };
+
+        let results = scan::each_service(
+            &client,
+            &service_codes,
+            options,
+            |client, service_code| async move {
+                client.applied_quotas(&service_code).await.map_err(|err| {
+                    format!(
+                        "failed to list quotas for service {} in region {}: {}",
+                        service_code,
+                        client.region(),
+                        err
+                    )
+                })
+            },
+        )
+        .await;
+
+        for definitions in results {
+            let definitions = match definitions {
+                Ok(definitions) => definitions,
+                Err(err) => {
+                    result.errors.push(err);
+                    continue;
+                }
+            };
+
+            let drifts = definitions
+                .iter()
+                .filter(|quota| options.filter.matches_quota(quota))
+                .filter(|quota| check_global || !quota.global)
+                .filter_map(|quota| evaluate(rules, region, quota));
+
+            for mut drift in drifts {
+                if drift.status == Status::Below {
+                    match client
+                        .open_increase_request(&drift.service_code, &drift.quota_code)
+                        .await
+                    {
+                        Ok(Some(requested)) if requested >= drift.minimum => {
+                            drift.status = Status::Requested(requested)
+                        }
+                        Ok(_) => {}
+                        Err(err) => result.errors.push(format!(
+                            "failed to list increase requests for quota {} in region {}: {}",
+                            drift.quota_code, region, err
+                        )),
+                    }
+                }
+
+                result.drifts.push(drift);
+            }
+        }
+    }
+
+    result.drifts.sort_by(|a, b| {
+        (
+            &a.account_id,
+            a.display_region(),
+            &a.service_code,
+            &a.quota_code,
+        )
+            .cmp(&(
+                &b.account_id,
+                b.display_region(),
+                &b.service_code,
+                &b.quota_code,
+            ))
+    });
+
+    result
+}
+
+// IncreaseRequest is the result of requesting a quota below its baseline be increased to it
+#[derive(Debug)]
+pub struct IncreaseRequest {
+    pub drift: Drift,
+    // the id of the request, or why it could not be filed
+    pub result: Result<String, String>,
+}
+
+// apply files an increase request to the baseline for every quota below it. Quotas with an
+// increase already open are skipped.
+pub async fn apply(scheduler: &Arc<Scheduler>, drifts: &[Drift]) -> Vec<IncreaseRequest> {
+    let mut requests = Vec::new();
+
+    for drift in drifts.iter().filter(|d| d.status == Status::Below) {
+        let client = servicequota::Client::new(&drift.region, Arc::clone(scheduler)).await;
+
+        let result = client
+            .request_increase(&drift.service_code, &drift.quota_code, drift.minimum)
+            .await
+            .map_err(|err| err.to_string());
+
+        requests.push(IncreaseRequest {
+            drift: drift.clone(),
+            result,
+        });
+    }
+
+    requests
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Matcher;
+
+    fn definition(quota_code: &str, value: Option<f64>, global: bool) -> QuotaDefinition {
+        QuotaDefinition {
+            arn: format!(
+                "arn:aws:servicequotas:us-east-1:123456789012:ec2/{}",
+                quota_code
+            ),
+            name: quota_code.to_string(),
+            service_code: "ec2".to_string(),
+            quota_code: quota_code.to_string(),
+            applied_value: value,
+            usage_metric: None,
+            global,
+        }
+    }
+
+    fn rule(quota_code: &str, region: Option<&str>, minimum: f64) -> BaselineRule {
+        BaselineRule {
+            matcher: Matcher {
+                quota_code: Some(quota_code.to_string()),
+                region: region.map(|r| r.to_string()),
+                ..Default::default()
+            },
+            minimum,
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let rules = vec![
+            rule("L-1216C47A", None, 512.0),
+            rule("L-1216C47A", Some("eu-west-1"), 1024.0),
+            rule("L-0263D0A3", Some("global"), 10.0),
+        ];
+
+        let drift = evaluate(
+            &rules,
+            "us-east-1",
+            &definition("L-1216C47A", Some(256.0), false),
+        )
+        .unwrap();
+        assert_eq!(drift.status, Status::Below);
+        assert_eq!(drift.minimum, 512.0);
+        assert_eq!(drift.account_id, "123456789012");
+
+        let drift = evaluate(
+            &rules,
+            "eu-west-1",
+            &definition("L-1216C47A", Some(512.0), false),
+        )
+        .unwrap();
+        assert_eq!(drift.status, Status::Below);
+        assert_eq!(drift.minimum, 1024.0);
+
+        let drift = evaluate(
+            &rules,
+            "us-east-1",
+            &definition("L-0263D0A3", Some(10.0), true),
+        )
+        .unwrap();
+        assert_eq!(drift.status, Status::Compliant);
+        assert_eq!(drift.display_region(), "global");
+
+        assert_eq!(
+            evaluate(
+                &rules,
+                "us-east-1",
+                &definition("L-0263D0A3", Some(10.0), false)
+            ),
+            None
+        );
+        assert_eq!(
+            evaluate(&rules, "us-east-1", &definition("L-1216C47A", None, false)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_service_codes() {
+        let mut rules = vec![BaselineRule {
+            matcher: Matcher {
+                service_code: Some("ec2".to_string()),
+                ..Default::default()
+            },
+            minimum: 1.0,
+        }];
+        assert_eq!(
+            service_codes(&rules),
+            Some(BTreeSet::from(["ec2".to_string()]))
+        );
+
+        rules.push(rule("L-1216C47A", None, 512.0));
+        assert_eq!(service_codes(&rules), None);
+    }
+}
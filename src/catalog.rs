@@ -0,0 +1,319 @@
+// Catalog caches the quotas discovered in each region on disk, so that ListServices and
+// ListServiceQuotas are only called once the cache expires.
+
+use aws_sdk_servicequotas::model::ServiceQuota;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+use std::time::Duration;
+
+// the version of the catalog file format, catalogs of other versions are ignored
+const CATALOG_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum CatalogError {
+    IoError(std::io::Error),
+    SerdeError(serde_json::Error),
+}
+
+impl Error for CatalogError {}
+impl Display for CatalogError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::IoError(e) => write!(f, "IoError: {}", e),
+            Self::SerdeError(e) => write!(f, "SerdeError: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for CatalogError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for CatalogError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::SerdeError(err)
+    }
+}
+
+// QuotaDefinition is a quota as returned by ListServiceQuotas
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotaDefinition {
+    pub arn: String,
+    pub name: String,
+    pub service_code: String,
+    pub quota_code: String,
+    pub applied_value: Option<f64>,
+    pub usage_metric: Option<UsageMetric>,
+    // global quotas apply to the whole account, and are listed again in every region
+    pub global: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageMetric {
+    pub namespace: String,
+    pub metric_name: String,
+    pub dimensions: HashMap<String, String>,
+    pub statistic: String,
+}
+
+impl From<&ServiceQuota> for QuotaDefinition {
+    fn from(quota: &ServiceQuota) -> Self {
+        Self {
+            arn: quota.quota_arn().unwrap_or_default().to_string(),
+            name: quota.quota_name().unwrap_or_default().to_string(),
+            service_code: quota.service_code().unwrap_or_default().to_string(),
+            quota_code: quota.quota_code().unwrap_or_default().to_string(),
+            applied_value: quota.value(),
+            usage_metric: quota.usage_metric().map(|usage_metric| UsageMetric {
+                namespace: usage_metric.metric_namespace().unwrap().to_string(),
+                metric_name: usage_metric.metric_name().unwrap().to_string(),
+                dimensions: usage_metric.metric_dimensions().unwrap().clone(),
+                statistic: usage_metric
+                    .metric_statistic_recommendation()
+                    .unwrap()
+                    .to_string(),
+            }),
+            global: quota.global_quota(),
+        }
+    }
+}
+
+// Catalog is every quota discovered in a region
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Catalog {
+    pub version: u32,
+    pub region: String,
+    // unix timestamp, in seconds, of when the catalog was discovered
+    pub created_at: i64,
+    pub service_codes: Vec<String>,
+    pub quotas: Vec<QuotaDefinition>,
+}
+
+impl Catalog {
+    pub fn new(region: &str, service_codes: Vec<String>, quotas: Vec<QuotaDefinition>) -> Self {
+        Self {
+            version: CATALOG_VERSION,
+            region: region.to_string(),
+            created_at: Utc::now().timestamp(),
+            service_codes,
+            quotas,
+        }
+    }
+
+    fn is_fresh(&self, now: i64, ttl: Duration) -> bool {
+        self.version == CATALOG_VERSION && now - self.created_at < ttl.as_secs() as i64
+    }
+
+    // service_codes_of returns the services of the given quotas, or None when a quota is not in
+    // the catalog
+    pub fn service_codes_of(&self, quota_codes: &[String]) -> Option<Vec<String>> {
+        let mut service_codes = Vec::new();
+
+        for quota_code in quota_codes {
+            let definition = self.quotas.iter().find(|q| &q.quota_code == quota_code)?;
+            if !service_codes.contains(&definition.service_code) {
+                service_codes.push(definition.service_code.clone());
+            }
+        }
+
+        Some(service_codes)
+    }
+}
+
+// CatalogCache stores a catalog per account and region, under the cache directory.
+#[derive(Debug, Clone)]
+pub struct CatalogCache {
+    dir: PathBuf,
+    ttl: Duration,
+    // ignore cached catalogs, while still storing the newly discovered ones
+    refresh: bool,
+}
+
+impl CatalogCache {
+    pub fn new(dir: PathBuf, account_id: &str, ttl: Duration, refresh: bool) -> Self {
+        Self {
+            dir: dir.join(account_id),
+            ttl,
+            refresh,
+        }
+    }
+
+    // refreshing ignores the cached catalogs, while still storing the newly discovered ones
+    pub fn refreshing(self) -> Self {
+        Self {
+            refresh: true,
+            ..self
+        }
+    }
+
+    fn path(&self, region: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", region))
+    }
+
+    // load returns the cached catalog of the region, unless it is missing, expired or being
+    // refreshed
+    pub fn load(&self, region: &str) -> Option<Catalog> {
+        if self.refresh {
+            return None;
+        }
+
+        let catalog = self.read(region)?;
+        catalog
+            .is_fresh(Utc::now().timestamp(), self.ttl)
+            .then_some(catalog)
+    }
+
+    // services returns the services of the given quotas from the cached catalog of the region,
+    // whatever its age, as quotas do not move between services
+    pub fn services(&self, region: &str, quota_codes: &[String]) -> Option<Vec<String>> {
+        self.read(region)?.service_codes_of(quota_codes)
+    }
+
+    fn read(&self, region: &str) -> Option<Catalog> {
+        let contents = std::fs::read_to_string(self.path(region)).ok()?;
+
+        let catalog = match serde_json::from_str::<Catalog>(&contents) {
+            Ok(catalog) => catalog,
+            Err(err) => {
+                log::warn!("ignoring unreadable catalog for region {}: {}", region, err);
+                return None;
+            }
+        };
+
+        (catalog.version == CATALOG_VERSION).then_some(catalog)
+    }
+
+    pub fn store(&self, catalog: &Catalog) -> Result<(), CatalogError> {
+        std::fs::create_dir_all(&self.dir)?;
+
+        // write to a temporary file first, so that concurrent runs never read a partial catalog
+        let path = self.path(&catalog.region);
+        let tmp_path = path.with_extension(format!("json.{}", std::process::id()));
+
+        std::fs::write(&tmp_path, serde_json::to_vec(catalog)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        Ok(())
+    }
+}
+
+// default_cache_dir is $XDG_CACHE_HOME/service-quotas, or ~/.cache/service-quotas
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let cache_home = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+
+    Some(cache_home.join("service-quotas"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn catalog(region: &str) -> Catalog {
+        Catalog::new(
+            region,
+            vec!["ec2".to_string()],
+            vec![QuotaDefinition {
+                arn: "arn:aws:servicequotas:us-east-1:123456789012:ec2/L-0263D0A3".to_string(),
+                name: "EC2-VPC Elastic IPs".to_string(),
+                service_code: "ec2".to_string(),
+                quota_code: "L-0263D0A3".to_string(),
+                applied_value: Some(5.0),
+                usage_metric: Some(UsageMetric {
+                    namespace: "AWS/Usage".to_string(),
+                    metric_name: "ResourceCount".to_string(),
+                    dimensions: HashMap::from([("Service".to_string(), "EC2".to_string())]),
+                    statistic: "Maximum".to_string(),
+                }),
+                global: false,
+            }],
+        )
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "service-quotas-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        let catalog = catalog("us-east-1");
+        let ttl = Duration::from_secs(3600);
+
+        assert!(catalog.is_fresh(catalog.created_at, ttl));
+        assert!(catalog.is_fresh(catalog.created_at + 3599, ttl));
+        assert!(!catalog.is_fresh(catalog.created_at + 3600, ttl));
+        assert!(!Catalog {
+            version: 0,
+            ..catalog.clone()
+        }
+        .is_fresh(catalog.created_at, ttl));
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let dir = cache_dir("store");
+        let cache = CatalogCache::new(dir.clone(), "123456789012", Duration::from_secs(60), false);
+        let catalog = catalog("us-east-1");
+
+        assert_eq!(cache.load("us-east-1"), None);
+
+        cache.store(&catalog).unwrap();
+        assert_eq!(cache.clone().refreshing().load("us-east-1"), None);
+        assert_eq!(cache.load("us-east-1"), Some(catalog));
+        assert_eq!(cache.load("eu-west-1"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_services() {
+        let dir = cache_dir("services");
+        let cache = CatalogCache::new(dir.clone(), "123456789012", Duration::from_secs(0), true);
+
+        assert_eq!(
+            cache.services("us-east-1", &["L-0263D0A3".to_string()]),
+            None
+        );
+
+        // the services are known from an expired catalog, even when it is being refreshed
+        cache.store(&catalog("us-east-1")).unwrap();
+        assert_eq!(
+            cache.services("us-east-1", &["L-0263D0A3".to_string()]),
+            Some(vec!["ec2".to_string()])
+        );
+        assert_eq!(
+            cache.services(
+                "us-east-1",
+                &["L-0263D0A3".to_string(), "L-2ACBD22F".to_string()]
+            ),
+            None
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_refresh() {
+        let dir = cache_dir("refresh");
+        let cache = CatalogCache::new(dir.clone(), "123456789012", Duration::from_secs(60), true);
+
+        cache.store(&catalog("us-east-1")).unwrap();
+        assert_eq!(cache.load("us-east-1"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 
 pub fn new() -> Command {
     Command::new(env!("CARGO_PKG_NAME"))
@@ -6,29 +6,178 @@ pub fn new() -> Command {
         .author(env!("CARGO_PKG_AUTHORS"))
         .version(env!("CARGO_PKG_VERSION"))
         .propagate_version(true)
-        .subcommands([utilization(), list_quotas()])
+        .subcommands([
+            utilization(),
+            list_quotas(),
+            defaults(),
+            baseline(),
+            parity(),
+            snapshot(),
+            diff(),
+        ])
 }
 
 fn common_args() -> Vec<Arg> {
-    vec![Arg::new("regions")
-        .short('r')
-        .long("regions")
-        .num_args(1..)
-        .default_value("us-east-1")
-        .value_parser(clap::builder::NonEmptyStringValueParser::new())
-        .help("The AWS region(s) to check quotas for, defaults to us-east-1")]
+    vec![
+        Arg::new("regions")
+            .short('r')
+            .long("regions")
+            .num_args(1..)
+            .default_value("us-east-1")
+            .value_parser(clap::builder::NonEmptyStringValueParser::new())
+            .help("The AWS region(s) to check quotas for, defaults to us-east-1. Use all for every region enabled for the account, or all-except=us-west-1,eu-west-3 to exclude some"),
+        Arg::new("services")
+            .short('s')
+            .long("services")
+            .num_args(1..)
+            .value_parser(clap::builder::NonEmptyStringValueParser::new())
+            .help("Only scan these service codes, e.g. ec2 lambda"),
+        Arg::new("exclude-services")
+            .long("exclude-services")
+            .num_args(1..)
+            .value_parser(clap::builder::NonEmptyStringValueParser::new())
+            .help("Do not scan these service codes"),
+        Arg::new("quotas")
+            .short('q')
+            .long("quotas")
+            .num_args(1..)
+            .value_parser(clap::builder::NonEmptyStringValueParser::new())
+            .help("Only scan these quota codes, e.g. L-1216C47A"),
+        Arg::new("name")
+            .long("name")
+            .value_parser(clap::builder::NonEmptyStringValueParser::new())
+            .help("Only scan quotas with a name matching this regular expression"),
+        Arg::new("config")
+            .short('c')
+            .long("config")
+            .value_parser(clap::builder::NonEmptyStringValueParser::new())
+            .help("The path to a TOML config file"),
+        Arg::new("concurrency")
+            .long("concurrency")
+            .value_parser(clap::value_parser!(u16).range(1..))
+            .help("The number of services to discover concurrently per region, defaults to 3"),
+        Arg::new("evaluation-concurrency")
+            .long("evaluation-concurrency")
+            .value_parser(clap::value_parser!(u16).range(1..))
+            .help("The number of quotas to evaluate concurrently, defaults to 10"),
+        Arg::new("rate-limit")
+            .long("rate-limit")
+            .num_args(1..)
+            .value_parser(crate::scheduler::parse_rate_limit)
+            .help("The requests per second per region for an AWS API, as api=rate, e.g. cloudwatch=10. APIs: servicequotas, cloudwatch, lambda, ec2"),
+    ]
+}
+
+// catalog_args are the arguments of the commands that read the catalog cache
+fn catalog_args() -> Vec<Arg> {
+    vec![
+        Arg::new("catalog-ttl")
+            .long("catalog-ttl")
+            .value_parser(clap::value_parser!(u64))
+            .help("The seconds to cache the discovered quotas for, 0 disables the cache, defaults to 86400"),
+        Arg::new("refresh-catalog")
+            .long("refresh-catalog")
+            .action(ArgAction::SetTrue)
+            .help("Discover the quotas from the AWS APIs, bypassing and refreshing the cache"),
+    ]
 }
 
 fn list_quotas() -> Command {
     Command::new("list-quotas")
         .about("List all supported quotas")
-        .args(&common_args())
+        .args(common_args())
+        .args(catalog_args())
+}
+
+fn defaults() -> Command {
+    Command::new("defaults")
+        .about("Compare the applied quota values against the AWS defaults")
+        .args(common_args())
+        .arg(Arg::new("all").long("all").action(ArgAction::SetTrue).help(
+            "Also list the quotas at the AWS default, not only those below the default or raised",
+        ))
+}
+
+fn baseline() -> Command {
+    Command::new("baseline")
+        .about("Enforce the minimum quota values declared in the config file, in the account of the AWS credentials")
+        .subcommand_required(true)
+        .subcommands([
+            Command::new("check")
+                .about("Report the quotas of the account below their baseline")
+                .args(common_args())
+                .arg(
+                    Arg::new("fail-on")
+                        .long("fail-on")
+                        .num_args(1..)
+                        .value_parser(["drift", "partial"])
+                        .help("The conditions that fail the process: quotas below their baseline (drift, exit code 5), or regions or services that could not be checked (partial)"),
+                ),
+            Command::new("apply")
+                .about("Request increases for the quotas of the account below their baseline")
+                .args(common_args()),
+        ])
+}
+
+fn parity() -> Command {
+    Command::new("parity")
+        .about("Compare the applied value and utilization of each quota across regions")
+        .args(common_args())
+        .args(catalog_args())
+        .args([
+            Arg::new("all")
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .help("Also list the quotas with the same applied value in every region"),
+            Arg::new("realtime")
+                .long("realtime")
+                .action(ArgAction::SetTrue)
+                .help("Prefer a live count from the resource APIs over CloudWatch usage metrics, where supported"),
+        ])
+}
+
+fn snapshot() -> Command {
+    Command::new("snapshot")
+        .about("Write the full scan result to a JSON file")
+        .args(common_args())
+        .args(catalog_args())
+        .args([
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .required(true)
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .help("The file to write the snapshot to"),
+            Arg::new("realtime")
+                .long("realtime")
+                .action(ArgAction::SetTrue)
+                .help("Prefer a live count from the resource APIs over CloudWatch usage metrics, where supported"),
+        ])
+}
+
+fn diff() -> Command {
+    Command::new("diff").about("Compare two snapshots").args([
+        Arg::new("before")
+            .required(true)
+            .value_parser(clap::value_parser!(std::path::PathBuf))
+            .help("The earlier snapshot"),
+        Arg::new("after")
+            .required(true)
+            .value_parser(clap::value_parser!(std::path::PathBuf))
+            .help("The later snapshot"),
+        Arg::new("utilization-delta")
+            .long("utilization-delta")
+            .default_value("10")
+            .value_parser(clap::value_parser!(u8).range(1..=100))
+            .help("Report utilization changes of at least this many percentage points"),
+    ])
 }
 
 fn utilization() -> Command {
     Command::new("utilization")
         .about("Check utilization of quotas")
-        .args(&common_args())
+        .args(common_args())
+        .args(catalog_args())
         .args(vec![
             Arg::new("threshold")
                 .short('t')
@@ -36,11 +185,57 @@ fn utilization() -> Command {
                 .default_value("75")
                 .value_parser(clap::value_parser!(u8).range(0..=100))
                 .help("The threshold to alert at for utlization of a service quota"),
+            Arg::new("headroom")
+                .long("headroom")
+                .value_parser(clap::value_parser!(f64))
+                .help("Also alert when fewer than this many units of a service quota remain"),
+            Arg::new("error-threshold")
+                .long("error-threshold")
+                .value_parser(clap::value_parser!(u8).range(0..=100))
+                .help("Escalate alerts to error severity at this utilization of a service quota"),
+            Arg::new("critical-threshold")
+                .long("critical-threshold")
+                .value_parser(clap::value_parser!(u8).range(0..=100))
+                .help("Escalate alerts to critical severity at this utilization of a service quota"),
+            Arg::new("resolve-threshold")
+                .long("resolve-threshold")
+                .value_parser(clap::value_parser!(u8).range(0..=100))
+                .help("Only resolve open alerts once utilization of a service quota is below this percentage"),
+            Arg::new("sustained-evaluations")
+                .long("sustained-evaluations")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .help("Only alert once a service quota has breached for this many consecutive runs"),
+            Arg::new("sustained-minutes")
+                .long("sustained-minutes")
+                .value_parser(clap::value_parser!(u32))
+                .help("Only alert once a service quota has breached for this many minutes"),
+            Arg::new("snoozes")
+                .long("snoozes")
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .help("A TOML file of snoozes, which suppress notifications for the quotas they match until they expire"),
             Arg::new("ignore")
                 .short('i')
                 .long("ignore")
                 .num_args(1..)
-                .help("The service quotas to ignore")
-                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+                .hide(true)
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .help("Deprecated, use --snoozes. The quota codes to snooze without an expiry"),
+            Arg::new("fail-on")
+                .long("fail-on")
+                .num_args(1..)
+                .value_parser(["breaches", "partial"])
+                .help("The conditions that fail the process: quotas over the threshold (breaches), or regions, services or quotas that could not be scanned (partial)"),
+            Arg::new("realtime")
+                .long("realtime")
+                .action(ArgAction::SetTrue)
+                .help("Prefer a live count from the resource APIs over CloudWatch usage metrics, where supported"),
+            Arg::new("alert-state")
+                .long("alert-state")
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .help("The file open alerts are persisted to between runs, defaults to ~/.local/state/service-quotas/alerts.json"),
+            Arg::new("digest")
+                .long("digest")
+                .value_parser(["account", "service"])
+                .help("Send a single notification per account, or per service of an account, rather than one per quota"),
         ])
 }
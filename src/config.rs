@@ -0,0 +1,490 @@
+// Configuration file for the rules that cannot be expressed on the command line, e.g.
+// thresholds for individual quotas.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Path, PathBuf};
+
+use crate::quotas::Quota;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    IoError(std::io::Error),
+    ParseError(toml::de::Error),
+}
+
+impl Error for ConfigError {}
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::IoError(e) => write!(f, "IoError: {}", e),
+            Self::ParseError(e) => write!(f, "ParseError: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::ParseError(err)
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub thresholds: Vec<ThresholdRule>,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub catalog: CatalogConfig,
+    #[serde(default)]
+    pub baseline: Vec<BaselineRule>,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceRule>,
+    #[serde(default)]
+    pub teams: Vec<Team>,
+    #[serde(default)]
+    pub ownership: Vec<OwnershipRule>,
+    #[serde(default)]
+    pub templates: TemplatesConfig,
+    #[serde(default)]
+    pub metadata: MetadataConfig,
+}
+
+// MetadataConfig attaches runbooks to quotas, and aliases and environments to accounts, for the
+// output and notifications. The aliases of accounts that are not set can be looked up.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct MetadataConfig {
+    pub alias_lookup: Option<AliasLookup>,
+    #[serde(default)]
+    pub runbooks: Vec<RunbookRule>,
+    #[serde(default)]
+    pub accounts: Vec<AccountMetadata>,
+}
+
+// AliasLookup is where account aliases are looked up: the IAM alias of the account of the
+// credentials, or the names of the accounts of the organization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AliasLookup {
+    Iam,
+    Organizations,
+}
+
+// RunbookRule is the runbook of the quotas it matches
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RunbookRule {
+    #[serde(flatten)]
+    pub matcher: Matcher,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AccountMetadata {
+    pub account_id: String,
+    pub alias: Option<String>,
+    pub environment: Option<String>,
+}
+
+// TemplatesConfig overrides the text of the notifications with Handlebars templates. The custom
+// details are added to the details of each alert, by name.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct TemplatesConfig {
+    pub summary: Option<String>,
+    pub digest_summary: Option<String>,
+    pub source: Option<String>,
+    #[serde(default)]
+    pub custom_details: BTreeMap<String, String>,
+    pub chat: Option<String>,
+    pub chat_resolved: Option<String>,
+}
+
+// Team is who owns the quotas assigned to it by the ownership rules, and where their alerts are
+// sent. The routing key can be read from an environment variable, to keep it out of the file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Team {
+    pub name: String,
+    pub routing_key: Option<String>,
+    pub routing_key_env: Option<String>,
+    pub slack_channel: Option<String>,
+}
+
+impl Team {
+    pub fn routing_key(&self) -> Option<String> {
+        match &self.routing_key_env {
+            Some(env) => std::env::var(env).ok(),
+            None => self.routing_key.clone(),
+        }
+    }
+}
+
+// OwnershipRule assigns the quotas it matches to a team. Rules with an account tag only match
+// the quotas of the accounts with that tag.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct OwnershipRule {
+    #[serde(flatten)]
+    pub matcher: Matcher,
+    pub account_tag: Option<AccountTag>,
+    pub team: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AccountTag {
+    pub key: String,
+    pub value: String,
+}
+
+// AlertsConfig sets the file the open alerts are persisted to between runs, the file of
+// snoozes, and how alerts are grouped into digests.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct AlertsConfig {
+    pub state_path: Option<PathBuf>,
+    pub snooze_path: Option<PathBuf>,
+    pub digest: Option<Digest>,
+}
+
+// Digest groups the alerts of an account, or of each service of an account, into a single
+// notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Digest {
+    Account,
+    Service,
+}
+
+impl std::str::FromStr for Digest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "account" => Ok(Self::Account),
+            "service" => Ok(Self::Service),
+            _ => Err(format!("invalid digest {}, expected account or service", s)),
+        }
+    }
+}
+
+// CatalogConfig sets the time to live of the catalog cache, in seconds, and the directory it is
+// stored in.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct CatalogConfig {
+    pub ttl: Option<u64>,
+    pub path: Option<PathBuf>,
+}
+
+// SchedulerConfig sets the concurrency of the scan, and the rate limits of each AWS API, in
+// requests per second per region, e.g. cloudwatch = 10.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct SchedulerConfig {
+    // the number of services discovered concurrently, per region
+    pub concurrency: Option<usize>,
+    // the number of quotas evaluated concurrently, across all regions
+    pub evaluation_concurrency: Option<usize>,
+    #[serde(default)]
+    pub rate_limits: BTreeMap<String, f64>,
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(contents)?)
+    }
+}
+
+// MaintenanceRule is a maintenance window for the quotas it matches. One-off windows start and
+// end at a datetime, recurring windows at a time of day, in UTC, on the days listed, or every day.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MaintenanceRule {
+    #[serde(flatten)]
+    pub matcher: Matcher,
+    pub start: toml::Value,
+    pub end: toml::Value,
+    #[serde(default)]
+    pub days: Vec<String>,
+    #[serde(default)]
+    pub action: MaintenanceAction,
+    pub reason: Option<String>,
+}
+
+// MaintenanceAction is what notifiers do with the alerts of quotas in a maintenance window:
+// send them at a low severity, or hold them until the window ends.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaintenanceAction {
+    Low,
+    #[default]
+    Hold,
+}
+
+// Matcher selects quotas by their identifiers. Fields that are not set match every quota.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct Matcher {
+    pub quota_code: Option<String>,
+    pub service_code: Option<String>,
+    pub account_id: Option<String>,
+    pub region: Option<String>,
+}
+
+impl Matcher {
+    pub async fn matches(&self, quota: &dyn Quota) -> bool {
+        self.matches_fields(
+            quota.quota_code().await,
+            quota.service_code().await,
+            quota.account_id().await,
+            quota.region().await,
+        )
+    }
+
+    pub fn matches_fields(
+        &self,
+        quota_code: &str,
+        service_code: &str,
+        account_id: &str,
+        region: &str,
+    ) -> bool {
+        matches_field(&self.quota_code, quota_code)
+            && matches_field(&self.service_code, service_code)
+            && matches_field(&self.account_id, account_id)
+            && matches_field(&self.region, region)
+    }
+}
+
+// Matcher is displayed as the fields it matches on, e.g. quota_code=L-0263D0A3 region=eu-west-1,
+// or * when it matches every quota
+impl Display for Matcher {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let fields = [
+            ("quota_code", &self.quota_code),
+            ("service_code", &self.service_code),
+            ("account_id", &self.account_id),
+            ("region", &self.region),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_ref().map(|v| format!("{}={}", name, v)))
+        .collect::<Vec<_>>();
+
+        match fields.is_empty() {
+            true => write!(f, "*"),
+            false => write!(f, "{}", fields.join(" ")),
+        }
+    }
+}
+
+fn matches_field(expected: &Option<String>, actual: &str) -> bool {
+    match expected {
+        Some(expected) => expected == actual,
+        None => true,
+    }
+}
+
+// ThresholdRule overrides the thresholds for the quotas it matches.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ThresholdRule {
+    #[serde(flatten)]
+    pub matcher: Matcher,
+    // alert when utilization is at or over this percentage
+    pub percentage: Option<u8>,
+    // alert when fewer than this many units of the quota remain
+    pub headroom: Option<f64>,
+    // escalate the alert to error, and to critical, when utilization is at or over these
+    // percentages
+    pub error: Option<u8>,
+    pub critical: Option<u8>,
+    // resolve an open alert only once utilization is below this percentage
+    pub resolve: Option<u8>,
+    // alert only once the quota has been breaching for this many consecutive evaluations, and
+    // for this many minutes
+    pub sustained_evaluations: Option<u32>,
+    pub sustained_minutes: Option<u32>,
+}
+
+// BaselineRule declares the minimum value of the quotas it matches, e.g. L-1216C47A >= 512.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BaselineRule {
+    #[serde(flatten)]
+    pub matcher: Matcher,
+    pub minimum: f64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            r#"
+            [[thresholds]]
+            quota_code = "L-F678F1CE"
+            headroom = 2
+
+            [[thresholds]]
+            service_code = "ec2"
+            region = "eu-west-1"
+            percentage = 90
+            critical = 95
+            resolve = 80
+            sustained_evaluations = 3
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.thresholds,
+            vec![
+                ThresholdRule {
+                    matcher: Matcher {
+                        quota_code: Some("L-F678F1CE".to_string()),
+                        ..Default::default()
+                    },
+                    percentage: None,
+                    headroom: Some(2.0),
+                    error: None,
+                    critical: None,
+                    resolve: None,
+                    sustained_evaluations: None,
+                    sustained_minutes: None,
+                },
+                ThresholdRule {
+                    matcher: Matcher {
+                        service_code: Some("ec2".to_string()),
+                        region: Some("eu-west-1".to_string()),
+                        ..Default::default()
+                    },
+                    percentage: Some(90),
+                    headroom: None,
+                    error: None,
+                    critical: Some(95),
+                    resolve: Some(80),
+                    sustained_evaluations: Some(3),
+                    sustained_minutes: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_ownership() {
+        let config = Config::parse(
+            r#"
+            [[teams]]
+            name = "payments"
+            routing_key_env = "PAYMENTS_ROUTING_KEY"
+            slack_channel = "C0123456789"
+
+            [[ownership]]
+            account_tag = { key = "team", value = "payments" }
+            service_code = "ec2"
+            team = "payments"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.teams,
+            vec![Team {
+                name: "payments".to_string(),
+                routing_key: None,
+                routing_key_env: Some("PAYMENTS_ROUTING_KEY".to_string()),
+                slack_channel: Some("C0123456789".to_string()),
+            }]
+        );
+        assert_eq!(
+            config.ownership,
+            vec![OwnershipRule {
+                matcher: Matcher {
+                    service_code: Some("ec2".to_string()),
+                    ..Default::default()
+                },
+                account_tag: Some(AccountTag {
+                    key: "team".to_string(),
+                    value: "payments".to_string(),
+                }),
+                team: "payments".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_scheduler() {
+        let config = Config::parse(
+            r#"
+            [scheduler]
+            concurrency = 5
+            evaluation_concurrency = 20
+
+            [scheduler.rate_limits]
+            cloudwatch = 10
+            servicequotas = 2.5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.scheduler,
+            SchedulerConfig {
+                concurrency: Some(5),
+                evaluation_concurrency: Some(20),
+                rate_limits: BTreeMap::from([
+                    ("cloudwatch".to_string(), 10.0),
+                    ("servicequotas".to_string(), 2.5),
+                ]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_baseline() {
+        let config = Config::parse(
+            r#"
+            [[baseline]]
+            quota_code = "L-1216C47A"
+            service_code = "ec2"
+            minimum = 512
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.baseline,
+            vec![BaselineRule {
+                matcher: Matcher {
+                    quota_code: Some("L-1216C47A".to_string()),
+                    service_code: Some("ec2".to_string()),
+                    ..Default::default()
+                },
+                minimum: 512.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        let config = Config::parse("").unwrap();
+        assert!(config.thresholds.is_empty());
+        assert!(config.baseline.is_empty());
+        assert_eq!(config.scheduler, SchedulerConfig::default());
+        assert_eq!(config.catalog, CatalogConfig::default());
+    }
+
+    #[test]
+    fn test_matches_field() {
+        assert!(matches_field(&None, "ec2"));
+        assert!(matches_field(&Some("ec2".to_string()), "ec2"));
+        assert!(!matches_field(&Some("ec2".to_string()), "lambda"));
+    }
+}
@@ -0,0 +1,223 @@
+// Defaults compares the quota values applied to the account against the AWS defaults, to find
+// the quotas that are below the default, e.g. in newly vended accounts, and the quotas that
+// have been raised.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+use crate::catalog::QuotaDefinition;
+use crate::quotas::GLOBAL_REGION;
+use crate::scan::{self, ScanOptions};
+use crate::scheduler::Scheduler;
+use crate::services::servicequota;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    // the applied value is lower than the AWS default
+    Below,
+    // the applied value has been raised above the AWS default
+    Raised,
+    // the applied value is the AWS default
+    Default,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Below => write!(f, "below default"),
+            Self::Raised => write!(f, "raised"),
+            Self::Default => write!(f, "default"),
+        }
+    }
+}
+
+// Comparison is the applied value of a quota against its AWS default
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub region: String,
+    pub service_code: String,
+    pub quota_code: String,
+    pub name: String,
+    pub applied: f64,
+    pub default: f64,
+    pub status: Status,
+}
+
+// compare matches the applied quotas of a service to their defaults by quota code. Quotas
+// without both an applied and a default value are not compared.
+pub fn compare(
+    region: &str,
+    applied: &[QuotaDefinition],
+    defaults: &[QuotaDefinition],
+) -> Vec<Comparison> {
+    applied
+        .iter()
+        .filter_map(|quota| {
+            let default = defaults
+                .iter()
+                .find(|d| d.quota_code == quota.quota_code)?
+                .applied_value?;
+            let applied = quota.applied_value?;
+
+            let status = if applied < default {
+                Status::Below
+            } else if applied > default {
+                Status::Raised
+            } else {
+                Status::Default
+            };
+
+            Some(Comparison {
+                region: match quota.global {
+                    true => GLOBAL_REGION.to_string(),
+                    false => region.to_string(),
+                },
+                service_code: quota.service_code.clone(),
+                quota_code: quota.quota_code.clone(),
+                name: quota.name.clone(),
+                applied,
+                default,
+                status,
+            })
+        })
+        .collect()
+}
+
+// Defaults is the comparisons of every quota scanned, and the regions and services that could
+// not be compared
+#[derive(Debug, Default)]
+pub struct Defaults {
+    pub comparisons: Vec<Comparison>,
+    pub errors: Vec<String>,
+}
+
+// scan compares the quotas of every service in each region. Global quotas are only compared in
+// the region global quotas are evaluated in.
+pub async fn scan(
+    regions: &[String],
+    scheduler: &Arc<Scheduler>,
+    options: &ScanOptions,
+) -> Defaults {
+    let mut result = Defaults::default();
+
+    for region in regions {
+        let client = servicequota::Client::new(region, Arc::clone(scheduler)).await;
+        let compare_global = scan::global_region(regions) == Some(region.as_str());
+
+        let service_codes = match &options.filter.services {
+            Some(services) => services.clone(),
+            None => match client.service_codes().await {
+                Ok(service_codes) => service_codes,
+                Err(err) => {
+                    result.errors.push(format!(
+                        "failed to list services in region {}: {}",
+                        region, err
+                    ));
+                    continue;
+                }
+            },
+        };
+
+        let results = scan::each_service(
+            &client,
+            &service_codes,
+            options,
+            |client, service_code| async move {
+                let quotas = async {
+                    let applied = client.applied_quotas(&service_code).await?;
+                    let defaults = client.default_quotas(&service_code).await?;
+                    Ok::<_, servicequota::ServiceQuotaError>((applied, defaults))
+                };
+
+                quotas.await.map_err(|err| {
+                    format!(
+                        "failed to compare quotas for service {} in region {}: {}",
+                        service_code,
+                        client.region(),
+                        err
+                    )
+                })
+            },
+        )
+        .await;
+
+        for quotas in results {
+            match quotas {
+                Ok((applied, defaults)) => {
+                    let applied = applied
+                        .into_iter()
+                        .filter(|quota| options.filter.matches_quota(quota))
+                        .filter(|quota| compare_global || !quota.global)
+                        .collect::<Vec<_>>();
+
+                    result
+                        .comparisons
+                        .extend(compare(region, &applied, &defaults));
+                }
+                Err(err) => result.errors.push(err),
+            }
+        }
+    }
+
+    result.comparisons.sort_by(|a, b| {
+        (a.status, &a.region, &a.service_code, &a.quota_code).cmp(&(
+            b.status,
+            &b.region,
+            &b.service_code,
+            &b.quota_code,
+        ))
+    });
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn definition(quota_code: &str, value: Option<f64>, global: bool) -> QuotaDefinition {
+        QuotaDefinition {
+            arn: format!(
+                "arn:aws:servicequotas:us-east-1:123456789012:ec2/{}",
+                quota_code
+            ),
+            name: quota_code.to_string(),
+            service_code: "ec2".to_string(),
+            quota_code: quota_code.to_string(),
+            applied_value: value,
+            usage_metric: None,
+            global,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let applied = vec![
+            definition("L-1", Some(1.0), false),
+            definition("L-2", Some(10.0), true),
+            definition("L-3", Some(5.0), false),
+            definition("L-4", None, false),
+            definition("L-5", Some(5.0), false),
+        ];
+        let defaults = vec![
+            definition("L-1", Some(5.0), false),
+            definition("L-2", Some(5.0), true),
+            definition("L-3", Some(5.0), false),
+            definition("L-4", Some(5.0), false),
+        ];
+
+        let comparisons = compare("us-east-1", &applied, &defaults)
+            .into_iter()
+            .map(|c| (c.quota_code, c.region, c.status))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            comparisons,
+            vec![
+                ("L-1".to_string(), "us-east-1".to_string(), Status::Below),
+                ("L-2".to_string(), "global".to_string(), Status::Raised),
+                ("L-3".to_string(), "us-east-1".to_string(), Status::Default),
+            ]
+        );
+    }
+}
@@ -0,0 +1,98 @@
+// Filter prunes the services and quotas of a scan before any API calls are made for them.
+
+use regex::Regex;
+
+use crate::catalog::QuotaDefinition;
+
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    // only these services are scanned, without listing the services of the region
+    pub services: Option<Vec<String>>,
+    pub exclude_services: Vec<String>,
+    // only these quota codes are scanned
+    pub quotas: Option<Vec<String>>,
+    // only quotas with a name matching the pattern are scanned
+    pub name: Option<Regex>,
+}
+
+impl Filter {
+    // is_partial is true when services are pruned, so that discovery does not see every quota
+    // of the region
+    pub fn is_partial(&self) -> bool {
+        self.services.is_some() || !self.exclude_services.is_empty()
+    }
+
+    pub fn matches_service(&self, service_code: &str) -> bool {
+        let included = match &self.services {
+            Some(services) => services.iter().any(|s| s == service_code),
+            None => true,
+        };
+
+        included && !self.exclude_services.iter().any(|s| s == service_code)
+    }
+
+    pub fn matches_quota(&self, definition: &QuotaDefinition) -> bool {
+        let included = match &self.quotas {
+            Some(quotas) => quotas.contains(&definition.quota_code),
+            None => true,
+        };
+
+        included
+            && self.matches_service(&definition.service_code)
+            && self
+                .name
+                .as_ref()
+                .map(|name| name.is_match(&definition.name))
+                .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn definition(service_code: &str, quota_code: &str, name: &str) -> QuotaDefinition {
+        QuotaDefinition {
+            arn: format!(
+                "arn:aws:servicequotas:us-east-1:123456789012:{}/{}",
+                service_code, quota_code
+            ),
+            name: name.to_string(),
+            service_code: service_code.to_string(),
+            quota_code: quota_code.to_string(),
+            applied_value: None,
+            usage_metric: None,
+            global: false,
+        }
+    }
+
+    #[test]
+    fn test_matches_service() {
+        let filter = Filter::default();
+        assert!(filter.matches_service("ec2"));
+        assert!(!filter.is_partial());
+
+        let filter = Filter {
+            services: Some(vec!["ec2".to_string(), "lambda".to_string()]),
+            exclude_services: vec!["lambda".to_string()],
+            ..Default::default()
+        };
+        assert!(filter.matches_service("ec2"));
+        assert!(!filter.matches_service("lambda"));
+        assert!(!filter.matches_service("vpc"));
+        assert!(filter.is_partial());
+    }
+
+    #[test]
+    fn test_matches_quota() {
+        let filter = Filter {
+            quotas: Some(vec!["L-0263D0A3".to_string(), "L-F678F1CE".to_string()]),
+            name: Some(Regex::new("(?i)elastic ip").unwrap()),
+            ..Default::default()
+        };
+
+        assert!(filter.matches_quota(&definition("ec2", "L-0263D0A3", "EC2-VPC Elastic IPs")));
+        assert!(!filter.matches_quota(&definition("vpc", "L-F678F1CE", "VPCs per Region")));
+        assert!(!filter.matches_quota(&definition("ec2", "L-1216C47A", "Elastic IPs")));
+    }
+}
@@ -1,77 +1,732 @@
 pub mod cli;
 
+mod baseline;
+mod catalog;
+mod config;
+mod defaults;
+mod filter;
+mod maintenance;
+mod metadata;
 mod notifiers;
+mod ownership;
+mod parity;
 mod quotas;
+mod regions;
+mod scan;
+mod scheduler;
 mod services;
+mod snapshot;
+mod snoozes;
+mod thresholds;
 mod util;
 
 #[macro_use]
 extern crate prettytable;
 
+use catalog::CatalogCache;
 use clap::ArgMatches;
-use notifiers::Notify;
+use config::{AliasLookup, Config, Digest, MaintenanceAction};
+use filter::Filter;
+use maintenance::Maintenance;
+use metadata::{Metadata, QuotaMetadata};
+use notifiers::{state::Scope, templates::Templates, DeliveryReport, Policy};
+use ownership::Ownership;
 use prettytable::{format, Cell, Row, Table};
-use quotas::Quota;
-use services::servicequota;
-use std::{error::Error, sync::Arc};
-use tokio::sync::Semaphore;
+use quotas::{Quota, UtilizationError};
+use regex::Regex;
+use regions::RegionSelector;
+use scan::ScanOptions;
+use scheduler::{Api, Scheduler};
+use services::{ec2, iam, organizations, sts};
+use snoozes::Snoozes;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use thresholds::{Threshold, Thresholds};
+
+// the default time to live of the catalog cache, in seconds
+const DEFAULT_CATALOG_TTL: u64 = 24 * 60 * 60;
 
 pub async fn list_quotas(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let regions = args.get_many::<String>("regions").unwrap();
+    let config = load_config(args)?;
+    let scheduler = new_scheduler(args, &config)?;
+    let regions = resolve_regions(args, &scheduler).await?;
 
-    // TODO: Move this complexity into the servicequota module
-    let mut handlers = Vec::new();
-    for region in regions {
-        println!("checking for quotas in region {}", region);
+    let options = ScanOptions {
+        catalog: catalog_cache(args, &config, &regions).await,
+        ..scan_options(args, &config, false, false)?
+    };
+    let result = scan::scan(&regions, &scheduler, options).await;
 
-        let client = servicequota::Client::new(region).await;
-        let service_codes = client.service_codes().await?;
+    for err in &result.errors {
+        println!("error: {}", err);
+    }
+    for (region, reason) in &result.regions_skipped {
+        println!("skipping region {}: {}", region, reason);
+    }
 
-        let permits = new_permits().await;
+    let account_ids = quota_account_ids(&result.quotas).await;
+    let metadata = account_metadata(&config, &account_ids).await;
 
-        for service_code in service_codes {
-            let client_ = client.clone();
-            let permits = Arc::clone(&permits);
+    print_list_quotas_table(result.quotas, &metadata).await;
+    print_throttles_table(&scheduler);
 
-            handlers.push(tokio::spawn(async move {
-                let _permits = permits.acquire().await.unwrap();
-                client_.quotas(&service_code).await
-            }));
-        }
+    Ok(())
+}
+
+// defaults compares the applied value of each quota against the AWS default, listing the
+// quotas below the default and the quotas that have been raised
+pub async fn defaults(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config(args)?;
+    let scheduler = new_scheduler(args, &config)?;
+    let regions = resolve_regions(args, &scheduler).await?;
+
+    let options = scan_options(args, &config, false, false)?;
+    let result = defaults::scan(&regions, &scheduler, &options).await;
+
+    for err in &result.errors {
+        println!("error: {}", err);
     }
 
-    let mut all_quotas = Vec::new();
-    for handler in handlers {
-        match handler.await {
-            Ok(result) => match result {
-                Ok(quotas) => all_quotas.extend(quotas),
-                Err(err) => println!("error: {}", err),
-            },
-            Err(err) => println!("error: {}", err),
-        };
+    let comparisons = result
+        .comparisons
+        .iter()
+        .filter(|c| args.get_flag("all") || c.status != defaults::Status::Default)
+        .collect::<Vec<_>>();
+
+    // the defaults are compared for the account of the credentials
+    let account_id = sts::Client::new(&util::default_region().await)
+        .await
+        .account_id()
+        .await?;
+    let metadata = account_metadata(&config, std::slice::from_ref(&account_id)).await;
+
+    print_defaults_table(&comparisons, &account_id, &metadata);
+    print_throttles_table(&scheduler);
+
+    Ok(())
+}
+
+// parity compares the applied value and utilization of each quota across the regions scanned,
+// listing the quotas whose applied value differs between regions
+pub async fn parity(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config(args)?;
+    let realtime = args.get_flag("realtime");
+    let scheduler = new_scheduler(args, &config)?;
+    let regions = resolve_regions(args, &scheduler).await?;
+
+    let options = ScanOptions {
+        catalog: catalog_cache(args, &config, &regions).await,
+        ..scan_options(args, &config, realtime, true)?
+    };
+    let result = scan::scan(&regions, &scheduler, options).await;
+
+    for err in &result.errors {
+        println!("error: {}", err);
     }
+    for (region, reason) in &result.regions_skipped {
+        println!("skipping region {}: {}", region, reason);
+    }
+
+    let mut observations = Vec::new();
+    for quota in &result.quotas {
+        observations.push(parity::Observation::new(&**quota).await);
+    }
+
+    let parities = parity::group(observations)
+        .into_iter()
+        .filter(|p| args.get_flag("all") || p.differs())
+        .collect::<Vec<_>>();
+
+    let account_ids = quota_account_ids(&result.quotas).await;
+    let metadata = account_metadata(&config, &account_ids).await;
+
+    print_parity_table(&parities, &metadata);
+    print_throttles_table(&scheduler);
+
+    Ok(())
+}
+
+// snapshot writes the full result of a scan to a JSON file
+pub async fn snapshot(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config(args)?;
+    let realtime = args.get_flag("realtime");
+    let scheduler = new_scheduler(args, &config)?;
+    let regions = resolve_regions(args, &scheduler).await?;
+
+    // a snapshot records the values applied now, e.g. to confirm that an approved increase took
+    // effect, so the quotas are discovered again rather than read from the catalog cache
+    let options = ScanOptions {
+        catalog: catalog_cache(args, &config, &regions)
+            .await
+            .map(CatalogCache::refreshing),
+        ..scan_options(args, &config, realtime, true)?
+    };
+    let result = scan::scan(&regions, &scheduler, options).await;
+
+    for err in &result.errors {
+        println!("error: {}", err);
+    }
+    for (region, reason) in &result.regions_skipped {
+        println!("skipping region {}: {}", region, reason);
+    }
+
+    let account_ids = quota_account_ids(&result.quotas).await;
+    let metadata = account_metadata(&config, &account_ids).await;
+
+    let mut quotas = Vec::new();
+    for quota in &result.quotas {
+        quotas.push(snapshot::QuotaSnapshot::new(&**quota, &metadata).await);
+    }
+
+    let path = args.get_one::<PathBuf>("output").unwrap();
+    snapshot::Snapshot::new(&regions, quotas).write(path)?;
+    println!("wrote {} quotas to {}", result.quotas.len(), path.display());
 
-    print_list_quotas_table(all_quotas).await;
+    print_throttles_table(&scheduler);
 
     Ok(())
 }
 
-// new_permits returns a new semaphore.
-// 3 concurrent requests to the AWS APIs feels like a good number to avoid getting
+// diff compares two snapshots, listing the quotas whose applied value changed, the quotas that
+// appeared or disappeared, and the utilization changes over --utilization-delta
+pub fn diff(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let before = snapshot::Snapshot::load(args.get_one::<PathBuf>("before").unwrap())?;
+    let after = snapshot::Snapshot::load(args.get_one::<PathBuf>("after").unwrap())?;
+    let utilization_delta = *args.get_one::<u8>("utilization-delta").unwrap();
+
+    let diff = snapshot::diff(&before, &after, utilization_delta);
+
+    if diff.is_empty() {
+        println!("no changes");
+        return Ok(());
+    }
+
+    print_diff_table(&diff);
+
+    Ok(())
+}
+
+// baseline_check reports the quotas below the minimum values declared in the config file. Each
+// quota below its baseline, without an increase requested, is counted as a breach.
+pub async fn baseline_check(args: &ArgMatches) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let config = load_config(args)?;
+    if config.baseline.is_empty() {
+        return Err("no baseline declared in the config file".into());
+    }
+
+    let scheduler = new_scheduler(args, &config)?;
+    let regions = resolve_regions(args, &scheduler).await?;
+
+    let options = scan_options(args, &config, false, false)?;
+    let result = baseline::check(&regions, &scheduler, &options, &config.baseline).await;
+
+    for err in &result.errors {
+        println!("error: {}", err);
+    }
+
+    let metadata = account_metadata(&config, &drift_account_ids(&result.drifts)).await;
+
+    print_baseline_table(&result.drifts, &metadata);
+    print_throttles_table(&scheduler);
+
+    Ok(Outcome {
+        drifts: result
+            .drifts
+            .iter()
+            .filter(|d| d.status == baseline::Status::Below)
+            .count(),
+        failures: result.errors.len(),
+        ..Outcome::default()
+    })
+}
+
+// baseline_apply requests an increase to the baseline for each quota below it. Requests that
+// could not be filed are counted as failures.
+pub async fn baseline_apply(args: &ArgMatches) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let config = load_config(args)?;
+    if config.baseline.is_empty() {
+        return Err("no baseline declared in the config file".into());
+    }
+
+    let scheduler = new_scheduler(args, &config)?;
+    let regions = resolve_regions(args, &scheduler).await?;
+
+    let options = scan_options(args, &config, false, false)?;
+    let result = baseline::check(&regions, &scheduler, &options, &config.baseline).await;
+
+    for err in &result.errors {
+        println!("error: {}", err);
+    }
+
+    let requests = baseline::apply(&scheduler, &result.drifts).await;
+    let metadata = account_metadata(&config, &drift_account_ids(&result.drifts)).await;
+
+    print_increase_requests_table(&requests, &metadata);
+    print_throttles_table(&scheduler);
+
+    Ok(Outcome {
+        failures: result.errors.len() + requests.iter().filter(|r| r.result.is_err()).count(),
+        ..Outcome::default()
+    })
+}
+
+// resolve_regions resolves --regions to the regions to scan. The regions of the account are only
+// described for all and all-except, skipping the regions that are not enabled for the account,
+// while the regions listed explicitly are scanned as given.
+async fn resolve_regions(
+    args: &ArgMatches,
+    scheduler: &Arc<Scheduler>,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let values = args
+        .get_many::<String>("regions")
+        .unwrap()
+        .cloned()
+        .collect::<Vec<_>>();
+    let selector = RegionSelector::parse(&values)?;
+
+    if let RegionSelector::Listed(regions) = selector {
+        return Ok(regions);
+    }
+
+    let client = ec2::Client::new(&util::default_region().await, Arc::clone(scheduler)).await;
+    let available = client
+        .regions()
+        .await
+        .map_err(|err| format!("failed to discover regions: {}", err))?;
+
+    let selection = selector.select(&available);
+    for (region, reason) in &selection.skipped {
+        println!("skipping region {}: {}", region, reason);
+    }
+
+    Ok(selection.regions)
+}
+
+// scan_options sets the concurrency of each stage of the scan, the catalog cache and the
+// filters, from the command line or the config file
+fn scan_options(
+    args: &ArgMatches,
+    config: &Config,
+    realtime: bool,
+    evaluate: bool,
+) -> Result<ScanOptions, Box<dyn std::error::Error>> {
+    Ok(ScanOptions {
+        realtime,
+        evaluate,
+        discovery_concurrency: concurrency(args, config),
+        evaluation_concurrency: evaluation_concurrency(args, config),
+        catalog: None,
+        filter: filter(args)?,
+    })
+}
+
+// filter prunes the services and quotas scanned, from --services, --exclude-services, --quotas
+// and --name
+fn filter(args: &ArgMatches) -> Result<Filter, Box<dyn std::error::Error>> {
+    let values = |id: &str| {
+        args.get_many::<String>(id)
+            .map(|values| values.cloned().collect::<Vec<_>>())
+    };
+
+    let name = match args.get_one::<String>("name") {
+        Some(pattern) => Some(Regex::new(pattern)?),
+        None => None,
+    };
+
+    Ok(Filter {
+        services: values("services"),
+        exclude_services: values("exclude-services").unwrap_or_default(),
+        quotas: values("quotas"),
+        name,
+    })
+}
+
+// catalog_cache returns the cache of discovered quotas for the account of the credentials. The
+// cache is disabled with a TTL of 0, or when the account cannot be identified. It is only built
+// by the commands that scan with the catalog, as identifying the account costs a call to STS.
+async fn catalog_cache(
+    args: &ArgMatches,
+    config: &Config,
+    regions: &[String],
+) -> Option<CatalogCache> {
+    let ttl = args
+        .get_one::<u64>("catalog-ttl")
+        .copied()
+        .or(config.catalog.ttl)
+        .unwrap_or(DEFAULT_CATALOG_TTL);
+    let refresh = args.get_flag("refresh-catalog");
+
+    if ttl == 0 && !refresh {
+        return None;
+    }
+
+    let dir = config
+        .catalog
+        .path
+        .clone()
+        .or_else(catalog::default_cache_dir)?;
+
+    let account_id = match sts::Client::new(regions.first()?).await.account_id().await {
+        Ok(account_id) => account_id,
+        Err(err) => {
+            log::warn!(
+                "disabling the catalog cache, failed to identify the account: {}",
+                err
+            );
+            return None;
+        }
+    };
+
+    Some(CatalogCache::new(
+        dir.join("catalog"),
+        &account_id,
+        Duration::from_secs(ttl),
+        refresh,
+    ))
+}
+
+// concurrency is the number of services discovered at once per region, from --concurrency or the
+// config file. 3 concurrent requests to the AWS APIs feels like a good number to avoid getting
 // rate limited.
-// TODO: Make this configurable
-async fn new_permits() -> Arc<Semaphore> {
-    Arc::new(Semaphore::new(3))
+fn concurrency(args: &ArgMatches, config: &Config) -> usize {
+    args.get_one::<u16>("concurrency")
+        .map(|c| *c as usize)
+        .or(config.scheduler.concurrency)
+        .unwrap_or(3)
+        .max(1)
+}
+
+// evaluation_concurrency is the number of quotas evaluated at once, across all regions, from
+// --evaluation-concurrency or the config file. The rate limits of the scheduler bound the
+// requests made to each API.
+fn evaluation_concurrency(args: &ArgMatches, config: &Config) -> usize {
+    args.get_one::<u16>("evaluation-concurrency")
+        .map(|c| *c as usize)
+        .or(config.scheduler.evaluation_concurrency)
+        .unwrap_or(10)
+        .max(1)
+}
+
+// new_scheduler creates the scheduler with the rate limits of the config file, overridden by
+// --rate-limit
+fn new_scheduler(
+    args: &ArgMatches,
+    config: &Config,
+) -> Result<Arc<Scheduler>, Box<dyn std::error::Error>> {
+    let mut rates = HashMap::new();
+
+    for (api, rate) in &config.scheduler.rate_limits {
+        let (api, rate) = scheduler::parse_rate_limit(&format!("{}={}", api, rate))?;
+        rates.insert(api, rate);
+    }
+
+    if let Some(rate_limits) = args.get_many::<(Api, f64)>("rate-limit") {
+        rates.extend(rate_limits.copied());
+    }
+
+    Ok(Arc::new(Scheduler::new(rates)))
+}
+
+// print_throttles_table reports the APIs that throttled requests during the run
+fn print_throttles_table(scheduler: &Scheduler) {
+    let throttles = scheduler.throttles();
+    if throttles.is_empty() {
+        return;
+    }
+
+    println!("throttles:");
+
+    let mut table = Table::new();
+    table.add_row(row!["API", "Region", "Throttles", "Rate"]);
+
+    for throttle in throttles {
+        table.add_row(Row::new(vec![
+            Cell::new(&throttle.api.to_string()),
+            Cell::new(&throttle.region),
+            Cell::new(&throttle.throttles.to_string()),
+            Cell::new(&format!("{:.1}/s", throttle.rate)),
+        ]));
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
+fn print_diff_table(diff: &snapshot::Diff) {
+    let mut table = Table::new();
+    table.add_row(row![
+        "Change",
+        "Account",
+        "Region",
+        "Quota Code",
+        "Quota Name",
+        "Before",
+        "After",
+        "Runbook"
+    ]);
+
+    for (before, after) in &diff.applied_value {
+        table.add_row(row![
+            "applied value",
+            account_cell(&after.account_id, &after.metadata),
+            after.region,
+            after.quota_code,
+            after.name,
+            optional_value(before.applied_value),
+            optional_value(after.applied_value),
+            runbook_cell(&after.metadata),
+        ]);
+    }
+
+    let percentage = |quota: &snapshot::QuotaSnapshot| match quota.percentage {
+        Some(percentage) => format!("{}%", percentage),
+        None => "-".to_string(),
+    };
+
+    for (before, after) in &diff.utilization {
+        table.add_row(row![
+            "utilization",
+            account_cell(&after.account_id, &after.metadata),
+            after.region,
+            after.quota_code,
+            after.name,
+            percentage(before),
+            percentage(after),
+            runbook_cell(&after.metadata),
+        ]);
+    }
+
+    for quota in &diff.appeared {
+        table.add_row(row![
+            "appeared",
+            account_cell(&quota.account_id, &quota.metadata),
+            quota.region,
+            quota.quota_code,
+            quota.name,
+            "-",
+            optional_value(quota.applied_value),
+            runbook_cell(&quota.metadata),
+        ]);
+    }
+
+    for quota in &diff.disappeared {
+        table.add_row(row![
+            "disappeared",
+            account_cell(&quota.account_id, &quota.metadata),
+            quota.region,
+            quota.quota_code,
+            quota.name,
+            optional_value(quota.applied_value),
+            "-",
+            runbook_cell(&quota.metadata),
+        ]);
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
+fn print_parity_table(parities: &[parity::Parity], metadata: &Metadata) {
+    let mut table = Table::new();
+    table.add_row(row![
+        "Account",
+        "Service",
+        "Quota Code",
+        "Quota Name",
+        "Lowest",
+        "Highest",
+        "Regions",
+        "Runbook"
+    ]);
+
+    let limit = |observation: Option<&parity::Observation>| match observation {
+        Some(o) => format!("{} ({})", optional_value(o.limit), o.region),
+        None => "-".to_string(),
+    };
+
+    for parity in parities {
+        let regions = parity
+            .regions
+            .iter()
+            .map(|o| {
+                let percentage = match o.percentage {
+                    Some(percentage) => format!("{}%", percentage),
+                    None => "-".to_string(),
+                };
+                format!("{}: {} ({})", o.region, optional_value(o.limit), percentage)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // the runbook is the one of the region with the lowest value, which is the one to raise
+        let region = match parity.lowest().or(parity.regions.first()) {
+            Some(observation) => observation.region.as_str(),
+            None => "",
+        };
+        let metadata = metadata.quota(
+            &parity.quota_code,
+            &parity.service_code,
+            &parity.account_id,
+            region,
+        );
+
+        table.add_row(row![
+            account_cell(&parity.account_id, &metadata),
+            parity.service_code,
+            parity.quota_code,
+            parity.name,
+            limit(parity.lowest()),
+            limit(parity.highest()),
+            regions,
+            runbook_cell(&metadata),
+        ]);
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
+fn print_baseline_table(drifts: &[baseline::Drift], metadata: &Metadata) {
+    let mut table = Table::new();
+    table.add_row(row![
+        "Account",
+        "Region",
+        "Quota Code",
+        "Quota Name",
+        "Applied",
+        "Baseline",
+        "Status",
+        "Runbook"
+    ]);
+
+    for drift in drifts {
+        let metadata = drift_metadata(drift, metadata);
+
+        table.add_row(row![
+            account_cell(&drift.account_id, &metadata),
+            drift.display_region(),
+            drift.quota_code,
+            drift.name,
+            drift.applied,
+            drift.minimum,
+            drift.status,
+            runbook_cell(&metadata),
+        ]);
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
+fn print_increase_requests_table(requests: &[baseline::IncreaseRequest], metadata: &Metadata) {
+    if requests.is_empty() {
+        println!("every quota meets its baseline, or has an increase requested");
+        return;
+    }
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Account",
+        "Region",
+        "Quota Code",
+        "Applied",
+        "Requested",
+        "Request",
+        "Runbook"
+    ]);
+
+    for request in requests {
+        let result = match &request.result {
+            Ok(id) => id.clone(),
+            Err(err) => format!("failed: {}", err),
+        };
+
+        let metadata = drift_metadata(&request.drift, metadata);
+
+        table.add_row(row![
+            account_cell(&request.drift.account_id, &metadata),
+            request.drift.display_region(),
+            request.drift.quota_code,
+            request.drift.applied,
+            request.drift.minimum,
+            result,
+            runbook_cell(&metadata),
+        ]);
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
 }
 
-async fn print_list_quotas_table(quotas: Vec<Box<dyn Quota>>) {
+fn print_defaults_table(
+    comparisons: &[&defaults::Comparison],
+    account_id: &str,
+    metadata: &Metadata,
+) {
     let mut table = Table::new();
-    table.set_titles(Row::new(vec![Cell::new("Arn"), Cell::new("Name")]));
+    table.add_row(row![
+        "Account",
+        "Region",
+        "Service",
+        "Quota Code",
+        "Quota Name",
+        "Applied",
+        "Default",
+        "Status",
+        "Runbook"
+    ]);
+
+    for comparison in comparisons {
+        let metadata = metadata.quota(
+            &comparison.quota_code,
+            &comparison.service_code,
+            account_id,
+            &comparison.region,
+        );
+
+        table.add_row(row![
+            account_cell(account_id, &metadata),
+            comparison.region,
+            comparison.service_code,
+            comparison.quota_code,
+            comparison.name,
+            comparison.applied,
+            comparison.default,
+            comparison.status,
+            runbook_cell(&metadata),
+        ]);
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
+async fn print_list_quotas_table(quotas: Vec<Box<dyn Quota>>, metadata: &Metadata) {
+    let mut table = Table::new();
+    table.set_titles(Row::new(vec![
+        Cell::new("Arn"),
+        Cell::new("Name"),
+        Cell::new("Account"),
+        Cell::new("Runbook"),
+    ]));
 
     for quota in quotas {
+        let metadata = metadata.quota(
+            quota.quota_code().await,
+            quota.service_code().await,
+            quota.account_id().await,
+            quota.region().await,
+        );
+
         table.add_row(Row::new(vec![
             Cell::new(quota.arn().await),
             Cell::new(quota.name().await),
+            Cell::new(&account_label(&metadata)),
+            Cell::new(runbook_cell(&metadata)),
         ]));
     }
 
@@ -79,83 +734,232 @@ async fn print_list_quotas_table(quotas: Vec<Box<dyn Quota>>) {
     table.printstd();
 }
 
-pub async fn utilization(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let regions = args.get_many::<String>("regions").unwrap();
-    let threshold = args.get_one::<u8>("threshold").unwrap();
-    let ignored_quotas = match args.try_get_many::<String>("ignore") {
-        Ok(Some(ignored_quotas)) => Some(ignored_quotas.map(|r| r.to_string()).collect::<Vec<_>>()),
-        _ => None,
+// Outcome summarises a completed utilization scan or baseline check, so that the caller can
+// decide how the process should exit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    // the number of quotas with utilization over the threshold
+    pub breaches: usize,
+    // the number of quotas below their baseline
+    pub drifts: usize,
+    // the number of regions, services and quotas that could not be scanned
+    pub failures: usize,
+}
+
+pub async fn utilization(args: &ArgMatches) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let config = load_config(args)?;
+    // the windows, teams and templates of the config file are checked before the scan, so that
+    // a mistake in them is reported without waiting for it
+    let maintenance = Maintenance::new(&config.maintenance, chrono::Utc::now())?;
+    let mut ownership = Ownership::new(&config.teams, &config.ownership)?;
+    let templates = Templates::new(&config.templates)?;
+    let thresholds = Thresholds::new(
+        Threshold {
+            percentage: *args.get_one::<u8>("threshold").unwrap(),
+            headroom: args.get_one::<f64>("headroom").copied(),
+            error: args.get_one::<u8>("error-threshold").copied(),
+            critical: args.get_one::<u8>("critical-threshold").copied(),
+            resolve: args.get_one::<u8>("resolve-threshold").copied(),
+            sustained_evaluations: args.get_one::<u32>("sustained-evaluations").copied(),
+            sustained_minutes: args.get_one::<u32>("sustained-minutes").copied(),
+        },
+        &config.thresholds,
+    )?;
+    let realtime = args.get_flag("realtime");
+    let scheduler = new_scheduler(args, &config)?;
+    let regions = resolve_regions(args, &scheduler).await?;
+    let mut snoozes = match args
+        .get_one::<PathBuf>("snoozes")
+        .or(config.alerts.snooze_path.as_ref())
+    {
+        Some(path) => Snoozes::load(path, chrono::Utc::now())?,
+        None => Snoozes::default(),
     };
+    if let Some(ignored) = args.get_many::<String>("ignore") {
+        log::warn!("--ignore is deprecated and will be removed, use --snoozes with an expiry");
+        snoozes.ignore(&ignored.cloned().collect::<Vec<_>>());
+    }
 
-    let mut handlers = Vec::new();
+    let options = ScanOptions {
+        catalog: catalog_cache(args, &config, &regions).await,
+        ..scan_options(args, &config, realtime, true)?
+    };
+    let options_filter = options.filter.clone();
+    let result = scan::scan(&regions, &scheduler, options).await;
 
-    // TODO: Move this complexity into the servicequota module
-    for region in regions {
-        println!("checking for quotas in region {}", region);
+    for err in &result.errors {
+        println!("error: {}", err);
+    }
+    for (region, reason) in &result.regions_skipped {
+        println!("skipping region {}: {}", region, reason);
+    }
 
-        let client = servicequota::Client::new(region).await;
-        let service_codes = client.service_codes().await?;
+    for snooze in snoozes.snoozes.iter().filter(|s| snoozes.is_expired(s)) {
+        println!(
+            "snooze {} owned by {} expired at {}: {}",
+            snooze.matcher,
+            snooze.owner,
+            snooze.expires.to_rfc3339(),
+            snooze.reason
+        );
+    }
 
-        let permits = new_permits().await;
+    let mut metadata = Metadata::new(&config.metadata);
+    for (window, ends_at) in maintenance.active() {
+        let action = match window.action {
+            MaintenanceAction::Hold => "notifications held",
+            MaintenanceAction::Low => "notifications sent at info severity",
+        };
+        println!(
+            "maintenance window {} until {}: {}{}",
+            window.matcher,
+            ends_at.to_rfc3339(),
+            action,
+            match &window.reason {
+                Some(reason) => format!(", {}", reason),
+                None => String::new(),
+            }
+        );
+    }
 
-        for service_code in service_codes {
-            let permits = Arc::clone(&permits);
-            let client_ = client.clone();
+    let mut outcome = Outcome {
+        failures: result.errors.len(),
+        ..Outcome::default()
+    };
 
-            let handler = tokio::spawn(async move {
-                utilization_per_service(&client_, &service_code, permits).await
-            });
-            handlers.push(handler)
-        }
+    // with no region scanned there is nothing to report on, which must not pass as a clean run
+    if result.nothing_scanned(&regions) {
+        return Err("failed to scan every region".into());
     }
 
-    let mut all_quotas = Vec::new();
-    for handler in handlers {
-        let result = handler.await?;
+    let all_quotas = &result.quotas;
+    let scope = alert_scope(&result, all_quotas, &options_filter).await;
 
-        match result {
-            Ok(quotas) => all_quotas.extend(quotas),
-            Err(err) => println!("error: {}", err),
+    // the tags of the accounts scanned are only listed when an ownership rule matches them
+    if ownership.matches_account_tags() {
+        let client = organizations::Client::new(&util::default_region().await).await;
+        for account_id in &scope.account_ids {
+            match client.account_tags(account_id).await {
+                Ok(tags) => ownership.set_account_tags(account_id, tags),
+                Err(err) => {
+                    println!(
+                        "error: failed to list the tags of account {}: {}",
+                        account_id, err
+                    );
+                    ownership.set_unknown_tags(account_id);
+                }
+            }
         }
     }
 
-    print_breached_quotas_table(&all_quotas, threshold).await;
-    notify_breached_quotas(&all_quotas, threshold, ignored_quotas.as_deref()).await?;
+    lookup_account_aliases(&mut metadata, &scope.account_ids).await;
 
-    Ok(())
-}
-
-async fn utilization_per_service(
-    client: &servicequota::Client,
-    service_code: &str,
-    permits: Arc<Semaphore>,
-) -> Result<Vec<Box<dyn Quota>>, Box<dyn Error + Sync + Send>> {
-    let _permits = permits.acquire().await.unwrap();
-    let quotas = client.quotas(service_code).await;
+    print_breached_quotas_table(all_quotas, &thresholds, &ownership, &metadata).await;
+    print_utilization_errors_table(all_quotas).await;
+    print_throttles_table(&scheduler);
+    print_snoozes_table(all_quotas, &thresholds, &snoozes).await;
+    let state_path = args
+        .get_one::<PathBuf>("alert-state")
+        .cloned()
+        .or_else(|| config.alerts.state_path.clone());
+    let digest = match args.get_one::<String>("digest") {
+        Some(digest) => Some(digest.parse::<Digest>()?),
+        None => config.alerts.digest,
+    };
+    let policy = Policy {
+        thresholds: thresholds.clone(),
+        snoozes,
+        ownership,
+        templates,
+        metadata,
+    };
+    // events that failed to be delivered make the run partial, rather than losing its outcome
+    outcome.failures += notify_breached_quotas(
+        all_quotas,
+        &policy,
+        &maintenance,
+        &scope,
+        state_path,
+        digest,
+    )
+    .await?;
 
-    match quotas {
-        Ok(quotas) => {
-            // TODO: This is a hack to get utilization in parallel
-            for quota in &quotas {
-                quota.utilization().await;
+    for quota in all_quotas {
+        if thresholds.breached(&**quota).await {
+            outcome.breaches += 1;
+        } else if let Err(err) = quota.utilization().await {
+            if err.is_failure() {
+                outcome.failures += 1;
             }
-
-            Ok(quotas)
         }
-        Err(err) => Err(Box::new(err)),
+    }
+
+    Ok(outcome)
+}
+
+// load_config loads the config file passed with --config, if any
+fn load_config(args: &ArgMatches) -> Result<Config, Box<dyn std::error::Error>> {
+    match args.get_one::<String>("config") {
+        Some(path) => Ok(Config::load(path)?),
+        None => Ok(Config::default()),
     }
 }
 
-async fn print_breached_quotas_table(quotas: &[Box<dyn Quota>], threshold: &u8) {
+// print_breached_quotas_table lists the quotas at or over their threshold, the same comparison
+// notifications use, where it used to only list those strictly over it
+async fn print_breached_quotas_table(
+    quotas: &[Box<dyn Quota>],
+    thresholds: &Thresholds,
+    ownership: &Ownership,
+    metadata: &Metadata,
+) {
     let mut table = Table::new();
-    table.add_row(row!["ARN", "Quota Name", "Utilization"]);
+    table.add_row(row![
+        "ARN",
+        "Quota Name",
+        "Severity",
+        "Utilization",
+        "Usage",
+        "Limit",
+        "Source",
+        "Team",
+        "Account",
+        "Runbook"
+    ]);
 
     for quota in quotas {
-        if quota.utilization().await > Some(*threshold) {
+        let Ok(utilization) = quota.utilization().await else {
+            continue;
+        };
+
+        if let Some(severity) = thresholds.threshold(&**quota).await.severity(&utilization) {
+            let team = ownership
+                .owner(
+                    quota.quota_code().await,
+                    quota.service_code().await,
+                    quota.account_id().await,
+                    quota.region().await,
+                )
+                .map(|team| team.name.as_str())
+                .unwrap_or("");
+            let metadata = metadata.quota(
+                quota.quota_code().await,
+                quota.service_code().await,
+                quota.account_id().await,
+                quota.region().await,
+            );
+
             table.add_row(Row::new(vec![
                 Cell::new(quota.arn().await),
                 Cell::new(quota.name().await),
-                Cell::new(&quota.utilization().await.unwrap().to_string()),
+                Cell::new(&severity.to_string()),
+                Cell::new(&utilization.percentage.to_string()),
+                Cell::new(&optional_value(utilization.usage)),
+                Cell::new(&optional_value(utilization.limit)),
+                Cell::new(&utilization.source.to_string()),
+                Cell::new(team),
+                Cell::new(&account_label(&metadata)),
+                Cell::new(runbook_cell(&metadata)),
             ]));
         }
     }
@@ -164,15 +968,299 @@ async fn print_breached_quotas_table(quotas: &[Box<dyn Quota>], threshold: &u8)
     table.printstd();
 }
 
+// account_label is the alias and environment of the account of a quota, e.g. payments (prod)
+fn account_label(metadata: &QuotaMetadata) -> String {
+    match (&metadata.account_alias, &metadata.environment) {
+        (Some(alias), Some(environment)) => format!("{} ({})", alias, environment),
+        (Some(label), None) | (None, Some(label)) => label.clone(),
+        (None, None) => String::new(),
+    }
+}
+
+// account_cell is the account id of a quota, followed by its label when it has one
+fn account_cell(account_id: &str, metadata: &QuotaMetadata) -> String {
+    match account_label(metadata).as_str() {
+        "" => account_id.to_string(),
+        label => format!("{}\n{}", account_id, label),
+    }
+}
+
+fn runbook_cell(metadata: &QuotaMetadata) -> &str {
+    metadata.runbook_url.as_deref().unwrap_or("")
+}
+
+// drift_metadata is the metadata of a quota checked against its baseline, global quotas match
+// the runbooks by the region they were listed in
+fn drift_metadata(drift: &baseline::Drift, metadata: &Metadata) -> QuotaMetadata {
+    metadata.quota(
+        &drift.quota_code,
+        &drift.service_code,
+        &drift.account_id,
+        &drift.region,
+    )
+}
+
+// account_metadata is the metadata of the config file, with the aliases of the accounts given
+// looked up
+async fn account_metadata(config: &Config, account_ids: &[String]) -> Metadata {
+    let mut metadata = Metadata::new(&config.metadata);
+    lookup_account_aliases(&mut metadata, account_ids).await;
+    metadata
+}
+
+// quota_account_ids returns the accounts of the quotas, in the order they were first seen
+async fn quota_account_ids(quotas: &[Box<dyn Quota>]) -> Vec<String> {
+    let mut account_ids = Vec::new();
+    for quota in quotas {
+        let account_id = quota.account_id().await.to_string();
+        if !account_ids.contains(&account_id) {
+            account_ids.push(account_id);
+        }
+    }
+    account_ids
+}
+
+fn drift_account_ids(drifts: &[baseline::Drift]) -> Vec<String> {
+    let mut account_ids = Vec::new();
+    for drift in drifts {
+        if !account_ids.contains(&drift.account_id) {
+            account_ids.push(drift.account_id.clone());
+        }
+    }
+    account_ids
+}
+
+// lookup_account_aliases looks up the aliases of the accounts scanned that have no alias in the
+// config file, from the IAM alias of the account of the credentials, or from the names of the
+// accounts of the organization. Accounts whose alias cannot be looked up are left without one.
+async fn lookup_account_aliases(metadata: &mut Metadata, account_ids: &[String]) {
+    let account_ids = account_ids
+        .iter()
+        .filter(|account_id| !metadata.has_alias(account_id))
+        .collect::<Vec<_>>();
+    if account_ids.is_empty() {
+        return;
+    }
+
+    let region = util::default_region().await;
+    match metadata.alias_lookup() {
+        Some(AliasLookup::Iam) => {
+            let account_id = match sts::Client::new(&region).await.account_id().await {
+                Ok(account_id) => account_id,
+                Err(err) => {
+                    println!("error: failed to identify the account: {}", err);
+                    return;
+                }
+            };
+            if !account_ids.contains(&&account_id) {
+                return;
+            }
+
+            match iam::Client::new(&region).await.account_alias().await {
+                Ok(Some(alias)) => metadata.set_alias(&account_id, alias),
+                Ok(None) => (),
+                Err(err) => println!(
+                    "error: failed to look up the alias of account {}: {}",
+                    account_id, err
+                ),
+            }
+        }
+        Some(AliasLookup::Organizations) => {
+            let client = organizations::Client::new(&region).await;
+            for account_id in account_ids {
+                match client.account_name(account_id).await {
+                    Ok(Some(name)) => metadata.set_alias(account_id, name),
+                    Ok(None) => (),
+                    Err(err) => println!(
+                        "error: failed to look up the alias of account {}: {}",
+                        account_id, err
+                    ),
+                }
+            }
+        }
+        None => (),
+    }
+}
+
+fn optional_value(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+// print_utilization_errors_table groups the quotas whose utilization could not be determined by
+// the cause of the error and service, so that IAM and throttling issues are not hidden as no data.
+async fn print_utilization_errors_table(quotas: &[Box<dyn Quota>]) {
+    let mut errors: BTreeMap<(&str, &str), Vec<UtilizationError>> = BTreeMap::new();
+
+    for quota in quotas {
+        if let Err(err) = quota.utilization().await {
+            errors
+                .entry((err.cause(), quota.service_code().await))
+                .or_default()
+                .push(err);
+        }
+    }
+
+    if errors.is_empty() {
+        return;
+    }
+
+    println!("errors:");
+
+    let mut table = Table::new();
+    table.add_row(row!["Cause", "Service", "Quotas", "Example"]);
+
+    for ((cause, service_code), errs) in errors {
+        table.add_row(Row::new(vec![
+            Cell::new(cause),
+            Cell::new(service_code),
+            Cell::new(&errs.len().to_string()),
+            Cell::new(&errs[0].to_string()),
+        ]));
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
+// print_snoozes_table reports each snooze, whether it has expired, and the number of breached
+// quotas it suppressed the notifications of in this run
+async fn print_snoozes_table(
+    quotas: &[Box<dyn Quota>],
+    thresholds: &Thresholds,
+    snoozes: &Snoozes,
+) {
+    if snoozes.snoozes.is_empty() {
+        return;
+    }
+
+    let mut suppressed = vec![0; snoozes.snoozes.len()];
+    for quota in quotas {
+        if !thresholds.breached(&**quota).await {
+            continue;
+        }
+
+        if let Some(index) = snoozes.find(&**quota).await {
+            suppressed[index] += 1;
+        }
+    }
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Snooze",
+        "Owner",
+        "Reason",
+        "Expires",
+        "Status",
+        "Suppressed"
+    ]);
+
+    for (snooze, suppressed) in snoozes.snoozes.iter().zip(suppressed) {
+        let status = match snoozes.is_expired(snooze) {
+            true => "expired",
+            false => "active",
+        };
+
+        table.add_row(Row::new(vec![
+            Cell::new(&snooze.matcher.to_string()),
+            Cell::new(&snooze.owner),
+            Cell::new(&snooze.reason),
+            Cell::new(
+                &match snooze.expires == chrono::DateTime::<chrono::Utc>::MAX_UTC {
+                    true => "never".to_string(),
+                    false => snooze.expires.to_rfc3339(),
+                },
+            ),
+            Cell::new(status),
+            Cell::new(&suppressed.to_string()),
+        ]));
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
 async fn notify_breached_quotas(
     quotas: &[Box<dyn Quota>],
-    threshold: &u8,
-    ignored_quotas: Option<&[String]>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // if we found a notifier, then use it to send notifications
-    if let Some(notifier) = notifiers::lookup_notifiers(threshold, ignored_quotas).await? {
-        notifier.notify(quotas).await?;
-    };
+    policy: &Policy,
+    maintenance: &Maintenance,
+    scope: &Scope,
+    state_path: Option<PathBuf>,
+    digest: Option<Digest>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    // every notifier is sent the same alerts, with the same maintenance windows, even when
+    // another notifier fails. The number of events that failed to be delivered is returned.
+    let mut errors = Vec::new();
+    let mut reports = Vec::new();
+    for notifier in notifiers::lookup_notifiers(policy, state_path, digest).await? {
+        match notifier.notify(quotas, scope, maintenance).await {
+            Ok(report) => reports.push(report),
+            Err(err) => errors.push(err.to_string()),
+        }
+    }
 
-    Ok(())
+    print_delivery_reports(&reports);
+
+    match errors.is_empty() {
+        true => Ok(reports.iter().map(|report| report.failed.len()).sum()),
+        false => Err(errors.join(", ").into()),
+    }
+}
+
+// print_delivery_reports lists the events each notifier delivered, and the events that failed,
+// which are sent again in the next run
+fn print_delivery_reports(reports: &[DeliveryReport]) {
+    for report in reports {
+        println!(
+            "{}: {} events delivered, {} failed",
+            report.notifier,
+            report.accepted.len(),
+            report.failed.len()
+        );
+    }
+
+    if reports.iter().all(|report| report.failed.is_empty()) {
+        return;
+    }
+
+    println!("failed deliveries:");
+
+    let mut table = Table::new();
+    table.add_row(row!["Notifier", "Action", "Dedup Key", "Error"]);
+
+    for report in reports {
+        for (event, error) in &report.failed {
+            table.add_row(Row::new(vec![
+                Cell::new(&report.notifier),
+                Cell::new(&event.action.to_string()),
+                Cell::new(&event.dedup_key),
+                Cell::new(error),
+            ]));
+        }
+    }
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
+// alert_scope is the accounts, regions and filter the scan observed completely. Global quotas
+// are in scope when the region they are evaluated in was scanned completely.
+async fn alert_scope(result: &scan::Scan, quotas: &[Box<dyn Quota>], filter: &Filter) -> Scope {
+    let mut account_ids = Vec::new();
+    for quota in quotas {
+        let account_id = quota.account_id().await.to_string();
+        if !account_ids.contains(&account_id) {
+            account_ids.push(account_id);
+        }
+    }
+
+    let mut scanned_regions = result.regions_complete.clone();
+    if result.global_region.is_some() {
+        scanned_regions.push(quotas::GLOBAL_REGION.to_string());
+    }
+
+    Scope {
+        account_ids,
+        regions: scanned_regions,
+        filter: filter.clone(),
+    }
 }
@@ -21,6 +21,40 @@ impl Display for CliError {
     }
 }
 
+// The exit codes of the process. 2 is reserved for usage errors, which are handled by clap.
+const EXIT_OK: i32 = 0;
+const EXIT_FATAL: i32 = 1;
+const EXIT_BREACHES: i32 = 3;
+const EXIT_PARTIAL: i32 = 4;
+const EXIT_DRIFT: i32 = 5;
+
+// exit_code maps the outcome of a utilization scan or baseline check to an exit code, for the
+// conditions chosen by --fail-on. Breaches and drift take precedence over a partial scan.
+fn exit_code(outcome: &service_quotas::Outcome, fail_on: &[String]) -> i32 {
+    let fail_on = |condition: &str| fail_on.iter().any(|c| c == condition);
+
+    if fail_on("breaches") && outcome.breaches > 0 {
+        return EXIT_BREACHES;
+    }
+
+    if fail_on("drift") && outcome.drifts > 0 {
+        return EXIT_DRIFT;
+    }
+
+    if fail_on("partial") && outcome.failures > 0 {
+        return EXIT_PARTIAL;
+    }
+
+    EXIT_OK
+}
+
+// fail_on returns the conditions of --fail-on
+fn fail_on(args: &clap::ArgMatches) -> Vec<String> {
+    args.get_many::<String>("fail-on")
+        .map(|conditions| conditions.cloned().collect())
+        .unwrap_or_default()
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::Builder::from_env(
@@ -34,15 +68,91 @@ async fn main() {
         None => Err(CliError::UnknownSubcommand),
         Some(("utilization", args)) => service_quotas::utilization(args)
             .await
+            .map(|outcome| exit_code(&outcome, &fail_on(args)))
             .map_err(|e| CliError::Runtime(e.to_string())),
         Some(("list-quotas", args)) => service_quotas::list_quotas(args)
             .await
+            .map(|_| EXIT_OK)
+            .map_err(|e| CliError::Runtime(e.to_string())),
+        Some(("defaults", args)) => service_quotas::defaults(args)
+            .await
+            .map(|_| EXIT_OK)
+            .map_err(|e| CliError::Runtime(e.to_string())),
+        Some(("parity", args)) => service_quotas::parity(args)
+            .await
+            .map(|_| EXIT_OK)
             .map_err(|e| CliError::Runtime(e.to_string())),
+        Some(("snapshot", args)) => service_quotas::snapshot(args)
+            .await
+            .map(|_| EXIT_OK)
+            .map_err(|e| CliError::Runtime(e.to_string())),
+        Some(("diff", args)) => service_quotas::diff(args)
+            .map(|_| EXIT_OK)
+            .map_err(|e| CliError::Runtime(e.to_string())),
+        Some(("baseline", args)) => match args.subcommand() {
+            Some(("check", args)) => service_quotas::baseline_check(args)
+                .await
+                .map(|outcome| exit_code(&outcome, &fail_on(args)))
+                .map_err(|e| CliError::Runtime(e.to_string())),
+            Some(("apply", args)) => service_quotas::baseline_apply(args)
+                .await
+                .map(|outcome| exit_code(&outcome, &["partial".to_string()]))
+                .map_err(|e| CliError::Runtime(e.to_string())),
+            _ => Err(CliError::UnknownSubcommand),
+        },
         _ => Err(CliError::UnknownSubcommand),
     };
 
-    if let Err(err) = result {
-        error!("Error: {}", err);
-        std::process::exit(1);
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            error!("Error: {}", err);
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use service_quotas::Outcome;
+
+    #[test]
+    fn test_exit_code() {
+        let clean = Outcome::default();
+        let breached = Outcome {
+            breaches: 1,
+            ..Outcome::default()
+        };
+        let partial = Outcome {
+            failures: 2,
+            ..Outcome::default()
+        };
+        let both = Outcome {
+            breaches: 1,
+            failures: 2,
+            ..Outcome::default()
+        };
+        let drifted = Outcome {
+            drifts: 1,
+            failures: 2,
+            ..Outcome::default()
+        };
+        let all = vec!["breaches".to_string(), "partial".to_string()];
+
+        assert_eq!(exit_code(&clean, &all), EXIT_OK);
+        assert_eq!(exit_code(&breached, &[]), EXIT_OK);
+        assert_eq!(exit_code(&breached, &all), EXIT_BREACHES);
+        assert_eq!(exit_code(&partial, &all), EXIT_PARTIAL);
+        assert_eq!(exit_code(&partial, &all[..1]), EXIT_OK);
+        assert_eq!(exit_code(&both, &all), EXIT_BREACHES);
+        assert_eq!(exit_code(&both, &all[1..]), EXIT_PARTIAL);
+
+        // drift from baseline check has its own condition, and is not a breach
+        let baseline = vec!["drift".to_string(), "partial".to_string()];
+        assert_eq!(exit_code(&drifted, &baseline), EXIT_DRIFT);
+        assert_eq!(exit_code(&drifted, &baseline[1..]), EXIT_PARTIAL);
+        assert_eq!(exit_code(&drifted, &all[..1]), EXIT_OK);
+        assert_eq!(exit_code(&breached, &baseline), EXIT_OK);
     }
 }
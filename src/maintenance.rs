@@ -0,0 +1,278 @@
+// Maintenance windows hold the notifications for the quotas they match, or send them at a low
+// severity, e.g. during a planned migration in an account and region. Breaches are still
+// reported in the output.
+
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Utc, Weekday};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::config::{MaintenanceAction, MaintenanceRule, Matcher};
+use crate::quotas::Quota;
+use crate::util;
+
+#[derive(Debug)]
+pub enum MaintenanceError {
+    InvalidWindow(String),
+}
+
+impl Error for MaintenanceError {}
+impl Display for MaintenanceError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::InvalidWindow(e) => write!(f, "InvalidWindow: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Schedule {
+    // a single window between two datetimes
+    Once {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+    // a window between two times of day, in UTC, on the days listed, or every day. Windows that
+    // end at or before they start end on the next day.
+    Recurring {
+        days: Vec<Weekday>,
+        start: NaiveTime,
+        end: NaiveTime,
+    },
+}
+
+impl Schedule {
+    fn parse(rule: &MaintenanceRule) -> Result<Self, MaintenanceError> {
+        let invalid = |value: &toml::Value| MaintenanceError::InvalidWindow(value.to_string());
+        let start = util::toml_datetime(&rule.start).ok_or_else(|| invalid(&rule.start))?;
+        let end = util::toml_datetime(&rule.end).ok_or_else(|| invalid(&rule.end))?;
+
+        if let (Ok(start), Ok(end)) = (
+            DateTime::parse_from_rfc3339(&start),
+            DateTime::parse_from_rfc3339(&end),
+        ) {
+            if !rule.days.is_empty() {
+                return Err(MaintenanceError::InvalidWindow(
+                    "days are only supported for recurring windows".to_string(),
+                ));
+            }
+
+            return match start < end {
+                true => Ok(Self::Once {
+                    start: start.with_timezone(&Utc),
+                    end: end.with_timezone(&Utc),
+                }),
+                false => Err(MaintenanceError::InvalidWindow(format!(
+                    "{} is not before {}",
+                    start, end
+                ))),
+            };
+        }
+
+        let days = rule
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| MaintenanceError::InvalidWindow(format!("invalid day {}", day)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::Recurring {
+            days,
+            start: parse_time(&start)?,
+            end: parse_time(&end)?,
+        })
+    }
+
+    // ends_at is the end of the occurrence of the window that now is in, if any
+    fn ends_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Once { start, end } => match *start <= now && now < *end {
+                true => Some(*end),
+                false => None,
+            },
+            Self::Recurring { days, start, end } => {
+                // an occurrence that started yesterday may not have ended yet
+                [now.date_naive(), now.date_naive() - Duration::days(1)]
+                    .into_iter()
+                    .filter(|date| days.is_empty() || days.contains(&date.weekday()))
+                    .find_map(|date| {
+                        let starts_at = date.and_time(*start).and_utc();
+                        let mut ends_at = date.and_time(*end).and_utc();
+                        if ends_at <= starts_at {
+                            ends_at += Duration::days(1);
+                        }
+
+                        match starts_at <= now && now < ends_at {
+                            true => Some(ends_at),
+                            false => None,
+                        }
+                    })
+            }
+        }
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, MaintenanceError> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .map_err(|err| MaintenanceError::InvalidWindow(format!("{}: {}", time, err)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub matcher: Matcher,
+    pub action: MaintenanceAction,
+    pub reason: Option<String>,
+    schedule: Schedule,
+}
+
+// Maintenance is the windows of the config file, evaluated at the time the run started so that
+// every notifier sees the same windows
+#[derive(Debug, Clone)]
+pub struct Maintenance {
+    windows: Vec<Window>,
+    now: DateTime<Utc>,
+}
+
+impl Maintenance {
+    pub fn new(rules: &[MaintenanceRule], now: DateTime<Utc>) -> Result<Self, MaintenanceError> {
+        let windows = rules
+            .iter()
+            .map(|rule| {
+                Ok(Window {
+                    matcher: rule.matcher.clone(),
+                    action: rule.action,
+                    reason: rule.reason.clone(),
+                    schedule: Schedule::parse(rule)?,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { windows, now })
+    }
+
+    // active is the windows that are open, and when they end
+    pub fn active(&self) -> impl Iterator<Item = (&Window, DateTime<Utc>)> {
+        self.windows
+            .iter()
+            .filter_map(|window| Some((window, window.schedule.ends_at(self.now)?)))
+    }
+
+    // action is what to do with the notifications of the quota, when it is in an open window.
+    // Holding takes precedence over sending at a low severity when windows overlap.
+    pub async fn action(&self, quota: &dyn Quota) -> Option<MaintenanceAction> {
+        self.action_of(
+            quota.quota_code().await,
+            quota.service_code().await,
+            quota.account_id().await,
+            quota.region().await,
+        )
+    }
+
+    // action_of is the action for the fields of a quota, e.g. of an open alert whose quota was
+    // not observed in this run
+    pub fn action_of(
+        &self,
+        quota_code: &str,
+        service_code: &str,
+        account_id: &str,
+        region: &str,
+    ) -> Option<MaintenanceAction> {
+        let mut action = None;
+
+        for (window, _) in self.active() {
+            if window
+                .matcher
+                .matches_fields(quota_code, service_code, account_id, region)
+            {
+                action = action.max(Some(window.action));
+            }
+        }
+
+        action
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+
+    fn at(datetime: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(datetime)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn maintenance(now: &str) -> Maintenance {
+        let config = Config::parse(
+            r#"
+            [[maintenance]]
+            account_id = "123456789012"
+            region = "eu-west-1"
+            start = 2026-11-01T22:00:00Z
+            end = 2026-11-02T02:00:00Z
+            reason = "migration"
+
+            [[maintenance]]
+            service_code = "ec2"
+            days = ["sat"]
+            start = "22:00"
+            end = "02:00"
+            action = "low"
+            "#,
+        )
+        .unwrap();
+
+        Maintenance::new(&config.maintenance, at(now)).unwrap()
+    }
+
+    #[test]
+    fn test_once() {
+        assert_eq!(maintenance("2026-11-01T21:59:59Z").active().count(), 0);
+
+        let maintenance = maintenance("2026-11-01T22:00:00Z");
+        let active = maintenance.active().collect::<Vec<_>>();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].0.action, MaintenanceAction::Hold);
+        assert_eq!(active[0].1, at("2026-11-02T02:00:00Z"));
+    }
+
+    #[test]
+    fn test_recurring() {
+        // 2026-10-17 is a Saturday
+        assert_eq!(maintenance("2026-10-17T21:00:00Z").active().count(), 0);
+        assert_eq!(
+            maintenance("2026-10-17T23:00:00Z")
+                .active()
+                .next()
+                .unwrap()
+                .1,
+            at("2026-10-18T02:00:00Z")
+        );
+        assert_eq!(
+            maintenance("2026-10-18T01:00:00Z")
+                .active()
+                .next()
+                .unwrap()
+                .1,
+            at("2026-10-18T02:00:00Z")
+        );
+        assert_eq!(maintenance("2026-10-18T23:00:00Z").active().count(), 0);
+    }
+
+    #[test]
+    fn test_invalid_window() {
+        let config = Config::parse(
+            r#"
+            [[maintenance]]
+            start = 2026-11-02T02:00:00Z
+            end = 2026-11-01T22:00:00Z
+            "#,
+        )
+        .unwrap();
+
+        assert!(Maintenance::new(&config.maintenance, Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+// Metadata enriches quotas with their runbook, and the alias and environment of their account,
+// from the config file, so that responders know which account a breach is in, and what to do
+// about it, without looking them up.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{AccountMetadata, AliasLookup, MetadataConfig, RunbookRule};
+
+// QuotaMetadata is the metadata of a quota, as added to the output and notifications. Fields
+// that are not set are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runbook_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_alias: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    alias_lookup: Option<AliasLookup>,
+    runbooks: Vec<RunbookRule>,
+    accounts: HashMap<String, AccountMetadata>,
+}
+
+impl Metadata {
+    pub fn new(config: &MetadataConfig) -> Self {
+        Self {
+            alias_lookup: config.alias_lookup,
+            runbooks: config.runbooks.clone(),
+            accounts: config
+                .accounts
+                .iter()
+                .map(|account| (account.account_id.clone(), account.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn alias_lookup(&self) -> Option<AliasLookup> {
+        self.alias_lookup
+    }
+
+    // has_alias is true when the account has an alias, set in the config file or looked up
+    pub fn has_alias(&self, account_id: &str) -> bool {
+        self.account(account_id).account_alias.is_some()
+    }
+
+    // set_alias sets the alias looked up for an account, the aliases of the config file take
+    // precedence
+    pub fn set_alias(&mut self, account_id: &str, alias: String) {
+        let account = self
+            .accounts
+            .entry(account_id.to_string())
+            .or_insert_with(|| AccountMetadata {
+                account_id: account_id.to_string(),
+                alias: None,
+                environment: None,
+            });
+
+        if account.alias.is_none() {
+            account.alias = Some(alias);
+        }
+    }
+
+    // account is the alias and environment of an account
+    pub fn account(&self, account_id: &str) -> QuotaMetadata {
+        match self.accounts.get(account_id) {
+            Some(account) => QuotaMetadata {
+                runbook_url: None,
+                account_alias: account.alias.clone(),
+                environment: account.environment.clone(),
+            },
+            None => QuotaMetadata::default(),
+        }
+    }
+
+    // quota is the metadata of a quota, with the runbook of the last rule matching it, so that
+    // later rules take precedence
+    pub fn quota(
+        &self,
+        quota_code: &str,
+        service_code: &str,
+        account_id: &str,
+        region: &str,
+    ) -> QuotaMetadata {
+        let runbook = self.runbooks.iter().rev().find(|rule| {
+            rule.matcher
+                .matches_fields(quota_code, service_code, account_id, region)
+        });
+
+        QuotaMetadata {
+            runbook_url: runbook.map(|rule| rule.url.clone()),
+            ..self.account(account_id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_quota() {
+        let config = Config::parse(
+            r#"
+            [[metadata.runbooks]]
+            service_code = "ec2"
+            url = "https://wiki.example.com/quotas/ec2"
+
+            [[metadata.runbooks]]
+            quota_code = "L-1216C47A"
+            url = "https://wiki.example.com/quotas/vcpus"
+
+            [[metadata.accounts]]
+            account_id = "111111111111"
+            alias = "payments-prod"
+            environment = "production"
+            "#,
+        )
+        .unwrap();
+        let mut metadata = Metadata::new(&config.metadata);
+        metadata.set_alias("111111111111", "payments".to_string());
+        metadata.set_alias("222222222222", "search-staging".to_string());
+
+        assert_eq!(
+            metadata.quota("L-1216C47A", "ec2", "111111111111", "us-east-1"),
+            QuotaMetadata {
+                runbook_url: Some("https://wiki.example.com/quotas/vcpus".to_string()),
+                account_alias: Some("payments-prod".to_string()),
+                environment: Some("production".to_string()),
+            }
+        );
+        assert_eq!(
+            metadata.quota("L-0263D0A3", "ec2", "222222222222", "us-east-1"),
+            QuotaMetadata {
+                runbook_url: Some("https://wiki.example.com/quotas/ec2".to_string()),
+                account_alias: Some("search-staging".to_string()),
+                environment: None,
+            }
+        );
+        assert_eq!(
+            metadata.quota("L-2ACBD22F", "lambda", "333333333333", "us-east-1"),
+            QuotaMetadata::default()
+        );
+        assert!(!metadata.has_alias("333333333333"));
+    }
+}
@@ -1,31 +1,168 @@
 pub mod pagerduty;
+pub mod reconcile;
+pub mod slack;
+pub mod state;
+pub mod templates;
 
+use crate::config::Digest;
+use crate::maintenance::Maintenance;
+use crate::metadata::Metadata;
+use crate::ownership::Ownership;
 use crate::quotas::Quota;
+use crate::snoozes::Snoozes;
+use crate::thresholds::Thresholds;
 use async_trait::async_trait;
+use state::Scope;
 use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+use templates::Templates;
 
 #[async_trait]
 pub trait Notify: Send + Sync {
-    async fn notify(&self, quota: &[Box<dyn Quota>]) -> Result<(), Box<dyn Error>>;
+    // notify sends the alerts for the quotas of a scan, resolving the open alerts that are in the
+    // scope of the scan but were not observed. Alerts of quotas in a maintenance window are held,
+    // or sent at info severity. Events that fail to be delivered are reported, and sent again
+    // in the next run, rather than stopping the others.
+    async fn notify(
+        &self,
+        quota: &[Box<dyn Quota>],
+        scope: &Scope,
+        maintenance: &Maintenance,
+    ) -> Result<DeliveryReport, Box<dyn Error>>;
 }
 
-pub async fn lookup_notifiers(
-    threshold: &u8,
-    ignored_quotas: Option<&[String]>,
-) -> Result<Option<Box<impl Notify>>, Box<dyn Error>> {
-    // Pagerduty Notifier: sourced from the PAGERDUTY_ROUTING_KEY environment variable.
-    if let Some(routing_key) = pd_routing_key() {
-        let pd_client = pagerduty::Client::new(&routing_key, threshold, ignored_quotas)?;
-        return Ok(Some(Box::new(pd_client)));
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Trigger,
+    Resolve,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Trigger => write!(f, "trigger"),
+            Self::Resolve => write!(f, "resolve"),
+        }
+    }
+}
+
+// Event is the trigger, or resolve, of an alert, or digest, by dedup key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub action: Action,
+    pub dedup_key: String,
+}
+
+// DeliveryReport is the events a notifier delivered in a run, and the events it failed to
+// deliver, with the error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryReport {
+    pub notifier: String,
+    pub accepted: Vec<Event>,
+    pub failed: Vec<(Event, String)>,
+}
+
+impl DeliveryReport {
+    pub fn new(notifier: &str) -> Self {
+        Self {
+            notifier: notifier.to_string(),
+            accepted: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    // record records the result of delivering an event, where Ok(false) is an event that was
+    // not sent, e.g. an unchanged alert, and returns whether the event did not fail
+    pub fn record<E: Display>(
+        &mut self,
+        action: Action,
+        dedup_key: &str,
+        result: Result<bool, E>,
+    ) -> bool {
+        let event = Event {
+            action,
+            dedup_key: dedup_key.to_string(),
+        };
+
+        match result {
+            Ok(true) => self.accepted.push(event),
+            Ok(false) => (),
+            Err(err) => {
+                self.failed.push((event, err.to_string()));
+                return false;
+            }
+        }
+
+        true
     }
+}
 
-    Ok(None)
+// Policy decides which quotas are alerted on, which team owns them, and the text and metadata
+// of their alerts, the same for every notifier
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub thresholds: Thresholds,
+    pub snoozes: Snoozes,
+    pub ownership: Ownership,
+    pub templates: Templates,
+    pub metadata: Metadata,
 }
 
-fn pd_routing_key() -> Option<String> {
-    if let Ok(routing_key) = std::env::var("PAGERDUTY_ROUTING_KEY") {
-        return Some(routing_key);
+pub async fn lookup_notifiers(
+    policy: &Policy,
+    state_path: Option<PathBuf>,
+    digest: Option<Digest>,
+) -> Result<Vec<Box<dyn Notify>>, Box<dyn Error>> {
+    let mut notifiers: Vec<Box<dyn Notify>> = Vec::new();
+    let state_path = || {
+        state_path
+            .clone()
+            .or_else(state::default_state_path)
+            .ok_or("no alert state path, set --alert-state or [alerts] state_path")
+    };
+
+    // Pagerduty Notifier: sourced from the PAGERDUTY_ROUTING_KEY environment variable, and the
+    // routing keys of the teams.
+    let routing_key = env_var("PAGERDUTY_ROUTING_KEY");
+    let team_routing_keys = policy
+        .ownership
+        .teams()
+        .iter()
+        .any(|team| team.routing_key().is_some());
+    if routing_key.is_some() || team_routing_keys {
+        let pd_client =
+            pagerduty::Client::new(routing_key.as_deref(), policy, state_path()?, digest)?;
+        notifiers.push(Box::new(pd_client));
     }
 
-    None
+    // Slack Notifier: sourced from the SLACK_BOT_TOKEN environment variable, posting to the
+    // SLACK_CHANNEL environment variable, and the channels of the teams. The open alerts are
+    // persisted next to those of PagerDuty, as each notifier sends its own events.
+    if let Some(token) = env_var("SLACK_BOT_TOKEN") {
+        let channel = env_var("SLACK_CHANNEL");
+        let team_channels = policy
+            .ownership
+            .teams()
+            .iter()
+            .any(|team| team.slack_channel.is_some());
+        if channel.is_some() || team_channels {
+            let slack_client = slack::Client::new(
+                &token,
+                channel.as_deref(),
+                policy,
+                state_path()?.with_extension("slack.json"),
+            )?;
+            notifiers.push(Box::new(slack_client));
+        }
+    }
+
+    Ok(notifiers)
+}
+
+fn env_var(name: &str) -> Option<String> {
+    match std::env::var(name) {
+        Ok(value) if !value.is_empty() => Some(value),
+        _ => None,
+    }
 }
@@ -1,17 +1,44 @@
 use async_trait::async_trait;
-use reqwest::header::{HeaderMap, HeaderValue, InvalidHeaderValue, CONTENT_TYPE};
+use reqwest::header::{HeaderMap, HeaderValue, InvalidHeaderValue, CONTENT_TYPE, RETRY_AFTER};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::convert::From;
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
 
-use crate::notifiers::Notify;
+use crate::config::Digest;
+use crate::maintenance::Maintenance;
+use crate::metadata::{Metadata, QuotaMetadata};
+use crate::notifiers::reconcile::{self, Deliver, Resolve, Trigger};
+use crate::notifiers::state::{Alert, AlertState, OpenDigest, Scope};
+use crate::notifiers::templates::{Context, DigestContext, TemplateError};
+use crate::notifiers::{Action, DeliveryReport, Notify, Policy};
 use crate::quotas::Quota;
+use crate::thresholds::Severity;
+
+const EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+// events that are rate limited, or hit a server error, are retried with an exponential backoff,
+// with jitter so that retries of concurrent runs are spread out
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// a hung request times out, and is retried, rather than blocking the run
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// once this many responses in a row are rate limits or server errors, the remaining events of
+// the run are not sent, rather than each waiting out its own retries
+const MAX_CONSECUTIVE_FAILURES: u32 = 6;
 
 #[derive(Debug)]
 pub enum ClientError {
     ReqwestError(reqwest::Error),
     InvalidHeaderValue(InvalidHeaderValue),
+    TemplateError(TemplateError),
+    // the event was not sent, as the Events API kept failing earlier in the run
+    CircuitOpen(u32),
 
     // https://developer.pagerduty.com/docs/ZG9jOjExMDI5NTgw-events-api-v2-overview#response-codes--retry-logic
     PagerdutyApiError(u16, String),
@@ -23,6 +50,12 @@ impl Display for ClientError {
         match self {
             Self::ReqwestError(e) => write!(f, "RequestError: {}", e),
             Self::InvalidHeaderValue(e) => write!(f, "InvalidHeaderValue: {}", e),
+            Self::TemplateError(e) => write!(f, "TemplateError: {}", e),
+            Self::CircuitOpen(failures) => write!(
+                f,
+                "CircuitOpen: not sent after {} consecutive failures of the Events API",
+                failures
+            ),
             Self::PagerdutyApiError(status_code, error) => {
                 write!(
                     f,
@@ -46,11 +79,45 @@ impl From<InvalidHeaderValue> for ClientError {
     }
 }
 
+impl From<TemplateError> for ClientError {
+    fn from(err: TemplateError) -> Self {
+        Self::TemplateError(err)
+    }
+}
+
 pub struct Client {
     client: reqwest::Client,
-    routing_key: String,
-    threshold: u8,
-    ignored_quotas: Option<Vec<String>>,
+    // the routing key of the alerts of quotas without an owning team with a routing key
+    routing_key: Option<String>,
+    policy: Policy,
+    // the file the open alerts are persisted to between runs
+    state_path: PathBuf,
+    // when set, the alerts are sent as a digest per group rather than individually
+    digest: Option<Digest>,
+    breaker: Breaker,
+}
+
+// Breaker counts the consecutive rate limits and server errors of the Events API across the
+// events of a run, and opens once there are too many, so that an outage of PagerDuty does not
+// stall the run with retries
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: AtomicU32,
+}
+
+impl Breaker {
+    fn is_open(&self) -> bool {
+        self.failures.load(Ordering::Relaxed) >= MAX_CONSECUTIVE_FAILURES
+    }
+
+    // record counts a response of the Events API, a retryable one is a failure and any other
+    // resets the count
+    fn record(&self, status_code: u16) {
+        match retryable(status_code) {
+            true => self.failures.fetch_add(1, Ordering::Relaxed),
+            false => self.failures.swap(0, Ordering::Relaxed),
+        };
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -58,7 +125,18 @@ pub struct NotifyBody {
     routing_key: String,
     event_action: String,
     dedup_key: String,
-    payload: Payload,
+    // resolves have no payload
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<Payload>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    links: Vec<Link>,
+}
+
+// Link is shown on the incident, e.g. the runbook of the quota
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    href: String,
+    text: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -66,7 +144,63 @@ pub struct Payload {
     summary: String,
     source: String,
     severity: String,
-    custom_details: CustomDetails,
+    custom_details: Details,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Details {
+    Quota(CustomDetails),
+    Digest(DigestDetails),
+}
+
+// DigestDetails lists the quotas of a digest, and the quotas added to and removed from it since
+// it was last sent, by dedup key
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DigestDetails {
+    account_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    account_alias: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    environment: Option<String>,
+    service: Option<String>,
+    team: Option<String>,
+    quotas: Vec<DigestQuota>,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DigestQuota {
+    quota_code: String,
+    quota_name: String,
+    service: String,
+    region: String,
+    severity: Option<String>,
+    utilization_percentage: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    runbook_url: Option<String>,
+}
+
+impl DigestQuota {
+    fn new(alert: &Alert, metadata: &Metadata) -> Self {
+        let metadata = metadata.quota(
+            &alert.quota_code,
+            &alert.service_code,
+            &alert.account_id,
+            &alert.region,
+        );
+
+        Self {
+            quota_code: alert.quota_code.clone(),
+            quota_name: alert.name.clone(),
+            service: alert.service_code.clone(),
+            region: alert.region.clone(),
+            severity: alert.severity.map(|s| s.to_string()),
+            utilization_percentage: alert.percentage,
+            runbook_url: metadata.runbook_url,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -78,132 +212,557 @@ pub struct CustomDetails {
     quota_name: String,
     quota_code: String,
     utilization_percentage: u8,
+    utilization_source: String,
+    usage: Option<f64>,
+    limit: Option<f64>,
     threshold: u8,
+    headroom_threshold: Option<f64>,
+    error_threshold: Option<u8>,
+    critical_threshold: Option<u8>,
     service_quota_url: String,
+    team: Option<String>,
+    #[serde(flatten)]
+    metadata: QuotaMetadata,
+    // the custom details of the templates
+    #[serde(flatten)]
+    templated: BTreeMap<String, String>,
 }
 
 impl Client {
     pub fn new(
-        routing_key: &str,
-        threshold: &u8,
-        ignored_quotas: Option<&[String]>,
+        routing_key: Option<&str>,
+        policy: &Policy,
+        state_path: PathBuf,
+        digest: Option<Digest>,
     ) -> Result<Client, ClientError> {
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json")?);
 
         let client = reqwest::Client::builder()
             .default_headers(headers)
+            .timeout(REQUEST_TIMEOUT)
             .build()?;
 
         Ok(Self {
             client,
-            threshold: *threshold,
-            routing_key: routing_key.to_string(),
-            ignored_quotas: ignored_quotas.map(|v| v.iter().map(|s| s.to_string()).collect()),
+            routing_key: routing_key.map(|r| r.to_string()),
+            policy: policy.clone(),
+            state_path,
+            digest,
+            breaker: Breaker::default(),
         })
     }
 
-    async fn dedup_key(&self, quota: &dyn Quota) -> String {
-        format!(
-            "{}-{}-{}",
-            quota.quota_code().await,
-            quota.region().await,
-            quota.account_id().await,
-        )
-    }
-
-    fn trigger_action(&self, utilization: Option<u8>) -> String {
-        if utilization >= Some(self.threshold) {
+    fn trigger_action(&self, breached: bool) -> String {
+        if breached {
             return String::from("trigger");
         }
 
         String::from("resolve")
     }
 
-    async fn ignored_quota(&self, quota: &dyn Quota) -> bool {
-        if let Some(ignored_quotas) = &self.ignored_quotas {
-            let quota_code = quota.quota_code().await;
-            ignored_quotas.contains(&quota_code.to_string())
-        } else {
-            false
-        }
+    // routing_key is the routing key of the team owning the alert, or the default routing key
+    fn routing_key(&self, team: Option<&str>) -> Option<String> {
+        team.and_then(|team| self.policy.ownership.team(team))
+            .and_then(|team| team.routing_key())
+            .or_else(|| self.routing_key.clone())
     }
-}
 
-#[async_trait]
-impl Notify for Client {
-    #[allow(clippy::redundant_field_names)]
-    async fn notify(&self, quotas: &[Box<dyn Quota>]) -> Result<(), Box<dyn Error>> {
-        let url = "https://events.pagerduty.com/v2/enqueue";
+    fn payload(&self, trigger: &Trigger) -> Result<Payload, ClientError> {
+        let alert = &trigger.alert;
+        let utilization = &trigger.utilization;
+        let threshold = &trigger.threshold;
+        let context = Context::from(trigger);
+        let templates = &self.policy.templates;
 
-        for quota in quotas {
-            if self.ignored_quota(&**quota).await {
-                continue;
+        Ok(Payload {
+            summary: templates.summary(&context)?,
+            source: templates.source(&context)?,
+            severity: alert.severity.unwrap_or(Severity::Warning).to_string(),
+            custom_details: Details::Quota(CustomDetails {
+                arn: trigger.arn.clone(),
+                account_id: alert.account_id.clone(),
+                service: alert.service_code.clone(),
+                region: alert.region.clone(),
+                quota_name: alert.name.clone(),
+                quota_code: alert.quota_code.clone(),
+                threshold: threshold.percentage,
+                headroom_threshold: threshold.headroom,
+                error_threshold: threshold.error,
+                critical_threshold: threshold.critical,
+                utilization_percentage: utilization.percentage,
+                utilization_source: utilization.source.to_string(),
+                usage: utilization.usage,
+                limit: utilization.limit,
+                service_quota_url: trigger.service_quota_url.clone(),
+                team: alert.team.clone(),
+                metadata: trigger.metadata.clone(),
+                templated: templates.custom_details(&context)?,
+            }),
+        })
+    }
+
+    // digest_payload summarises the open alerts of a digest
+    fn digest_payload(
+        &self,
+        digest: Digest,
+        group: &OpenDigest,
+        previous: &OpenDigest,
+        state: &AlertState,
+    ) -> Result<Payload, ClientError> {
+        let alerts = group
+            .dedup_keys
+            .iter()
+            .filter_map(|dedup_key| state.alerts.get(dedup_key).map(|a| (dedup_key, a)))
+            .collect::<Vec<_>>();
+
+        let account_id = alerts[0].1.account_id.clone();
+        let account = self.policy.metadata.account(&account_id);
+        let service = match digest {
+            Digest::Account => None,
+            Digest::Service => Some(alerts[0].1.service_code.clone()),
+        };
+        let severity = alerts
+            .iter()
+            .filter_map(|(_, alert)| alert.severity)
+            .max()
+            .unwrap_or(Severity::Warning);
+
+        let context = DigestContext {
+            account_id: account_id.clone(),
+            account_alias: account.account_alias.clone(),
+            environment: account.environment.clone(),
+            service: service.clone(),
+            team: group.team.clone(),
+            severity: severity.to_string(),
+            count: alerts.len(),
+            quotas: alerts
+                .iter()
+                .map(|(dedup_key, alert)| {
+                    let metadata = self.policy.metadata.quota(
+                        &alert.quota_code,
+                        &alert.service_code,
+                        &alert.account_id,
+                        &alert.region,
+                    );
+                    Context::new("triggered", dedup_key, alert, &metadata)
+                })
+                .collect(),
+        };
+        let templates = &self.policy.templates;
+
+        Ok(Payload {
+            summary: templates.digest_summary(&context)?,
+            source: templates.source(&context)?,
+            severity: severity.to_string(),
+            custom_details: Details::Digest(DigestDetails {
+                account_id,
+                account_alias: account.account_alias,
+                environment: account.environment,
+                service,
+                team: group.team.clone(),
+                quotas: alerts
+                    .into_iter()
+                    .map(|(_, alert)| DigestQuota::new(alert, &self.policy.metadata))
+                    .collect(),
+                added: group
+                    .dedup_keys
+                    .iter()
+                    .filter(|dedup_key| !previous.dedup_keys.contains(dedup_key))
+                    .cloned()
+                    .collect(),
+                removed: previous
+                    .dedup_keys
+                    .iter()
+                    .filter(|dedup_key| !group.dedup_keys.contains(dedup_key))
+                    .cloned()
+                    .collect(),
+            }),
+        })
+    }
+
+    // digest_links are the runbooks of the quotas of a digest, each listed once
+    fn digest_links(&self, group: &OpenDigest, state: &AlertState) -> Vec<Link> {
+        let mut links: Vec<Link> = Vec::new();
+        for alert in group
+            .dedup_keys
+            .iter()
+            .filter_map(|dedup_key| state.alerts.get(dedup_key))
+        {
+            let metadata = self.policy.metadata.quota(
+                &alert.quota_code,
+                &alert.service_code,
+                &alert.account_id,
+                &alert.region,
+            );
+            if let Some(runbook_url) = metadata.runbook_url {
+                if !links.iter().any(|link| link.href == runbook_url) {
+                    links.push(Link {
+                        href: runbook_url,
+                        text: format!("Runbook: {}", alert.service_code),
+                    });
+                }
             }
+        }
 
-            let trigger_action = self.trigger_action(quota.utilization().await);
-            let dedup_key = self.dedup_key(&**quota).await;
+        links
+    }
 
-            let Some(utilization) = quota.utilization().await else {
-                return Ok(());
-            };
+    async fn send(
+        &self,
+        routing_key: &str,
+        event_action: &str,
+        dedup_key: &str,
+        payload: Option<Payload>,
+        links: Vec<Link>,
+    ) -> Result<(), ClientError> {
+        let body = NotifyBody {
+            routing_key: routing_key.to_string(),
+            event_action: event_action.to_string(),
+            dedup_key: dedup_key.to_string(),
+            payload,
+            links,
+        };
 
-            let payload = NotifyBody {
-                routing_key: self.routing_key.clone(),
-                event_action: trigger_action,
-                dedup_key: dedup_key,
-                payload: Payload {
-                    summary: format!(
-                        "Service Quota Utilization {}%: {} - {} in {} - {}",
-                        utilization,
-                        quota.quota_code().await,
-                        quota.name().await,
-                        quota.account_id().await,
-                        quota.region().await,
-                    ),
-                    source: "https://github.com/robpickerill/service-quotas".to_string(),
-                    severity: "warning".to_string(),
-                    custom_details: CustomDetails {
-                        arn: quota.arn().await.to_string(),
-                        account_id: quota.account_id().await.to_string(),
-                        service: quota.service_code().await.to_string(),
-                        region: quota.region().await.to_string(),
-                        quota_name: quota.name().await.to_string(),
-                        quota_code: quota.quota_code().await.to_string(),
-                        threshold: self.threshold,
-                        utilization_percentage: utilization,
-                        service_quota_url: service_quota_url(&**quota).await,
-                    },
-                },
-            };
+        let mut attempt = 1;
+        loop {
+            if self.breaker.is_open() {
+                return Err(ClientError::CircuitOpen(MAX_CONSECUTIVE_FAILURES));
+            }
+
+            let retry_after = match self.client.post(EVENTS_URL).json(&body).send().await {
+                Ok(response) => {
+                    let status_code = response.status().as_u16();
+                    self.breaker.record(status_code);
+                    if status_code == 202 {
+                        return Ok(());
+                    }
 
-            let result = self.client.post(url).json(&payload).send().await;
-            match result {
-                Ok(r) => {
-                    if r.status().as_u16() != 202 {
-                        return Err(Box::new(ClientError::PagerdutyApiError(
-                            r.status().as_u16(),
-                            r.text().await?,
-                        )));
+                    let retry_after = retry_after(&response);
+                    if !retryable(status_code) || attempt == MAX_ATTEMPTS {
+                        return Err(ClientError::PagerdutyApiError(
+                            status_code,
+                            response.text().await?,
+                        ));
                     }
+                    retry_after
                 }
-                Err(e) => {
-                    return Err(Box::new(ClientError::ReqwestError(e)));
+                Err(err) if (err.is_connect() || err.is_timeout()) && attempt < MAX_ATTEMPTS => {
+                    None
                 }
+                Err(err) => return Err(err.into()),
+            };
+
+            let delay = backoff(attempt, fastrand::f64());
+            tokio::time::sleep(retry_delay(delay, retry_after)).await;
+            attempt += 1;
+        }
+    }
+
+    // send_digests triggers the digest of each group of open alerts that has an alert still
+    // breaching in this run, or whose alerts changed, and resolves the digests that no longer have open
+    // alerts. A digest keeps its dedup key as alerts are added and removed, so that PagerDuty
+    // updates the open incident.
+    async fn send_digests(
+        &self,
+        digest: Digest,
+        breaching: &HashSet<String>,
+        state: &mut AlertState,
+        report: &mut DeliveryReport,
+    ) {
+        let groups = state.groups(digest);
+
+        for (digest_key, group) in &groups {
+            let previous = state.digests.get(digest_key).cloned().unwrap_or_default();
+            if previous == *group && !group.dedup_keys.iter().any(|k| breaching.contains(k)) {
+                continue;
+            }
+
+            let Some(routing_key) = self.routing_key(group.team.as_deref()) else {
+                continue;
+            };
+
+            let result = async {
+                let payload = self.digest_payload(digest, group, &previous, state)?;
+                self.send(
+                    &routing_key,
+                    &self.trigger_action(true),
+                    digest_key,
+                    Some(payload),
+                    self.digest_links(group, state),
+                )
+                .await?;
+                Ok::<_, ClientError>(true)
+            }
+            .await;
+
+            // a digest that failed is sent again in the next run, as it still differs
+            if report.record(Action::Trigger, digest_key, result) {
+                state.digests.insert(digest_key.clone(), group.clone());
+            }
+        }
+
+        self.resolve_digests(&groups, state, report).await;
+    }
+
+    // resolve_digests resolves the open digests that are not in the groups of this run, which
+    // is every open digest once digests are turned off
+    async fn resolve_digests(
+        &self,
+        groups: &BTreeMap<String, OpenDigest>,
+        state: &mut AlertState,
+        report: &mut DeliveryReport,
+    ) {
+        let resolved = state
+            .digests
+            .iter()
+            .filter(|(digest_key, _)| !groups.contains_key(*digest_key))
+            .map(|(digest_key, group)| (digest_key.clone(), group.team.clone()))
+            .collect::<Vec<_>>();
+
+        for (digest_key, team) in resolved {
+            let result = match self.routing_key(team.as_deref()) {
+                Some(routing_key) => self
+                    .send(
+                        &routing_key,
+                        &self.trigger_action(false),
+                        &digest_key,
+                        None,
+                        Vec::new(),
+                    )
+                    .await
+                    .map(|_| true),
+                None => Ok(false),
+            };
+
+            if report.record(Action::Resolve, &digest_key, result) {
+                state.digests.remove(&digest_key);
+            }
+        }
+    }
+}
+
+// retryable is true for the responses of the Events API that are worth retrying, rate limits
+// and server errors
+// https://developer.pagerduty.com/docs/ZG9jOjExMDI5NTgw-events-api-v2-overview#response-codes--retry-logic
+fn retryable(status_code: u16) -> bool {
+    status_code == 429 || (500..600).contains(&status_code)
+}
+
+// retry_after is the delay asked for by the Retry-After header of a response, in seconds
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+// retry_delay is the backoff, or the Retry-After of the response when it is longer, up to the
+// maximum backoff so that a single response cannot stall the run
+fn retry_delay(backoff: Duration, retry_after: Option<Duration>) -> Duration {
+    match retry_after {
+        Some(retry_after) => retry_after.max(backoff).min(MAX_BACKOFF),
+        None => backoff,
+    }
+}
+
+// backoff is the delay before the next attempt, doubling from the initial backoff up to the
+// maximum, of which a random half is jitter, from a jitter between 0 and 1
+fn backoff(attempt: u32, jitter: f64) -> Duration {
+    let delay = INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_BACKOFF);
+
+    delay / 2 + (delay / 2).mul_f64(jitter)
+}
+
+// links are the runbook of the quota, when set, and the quota in the AWS console
+fn links(trigger: &Trigger) -> Vec<Link> {
+    let mut links = Vec::new();
+    if let Some(runbook_url) = &trigger.metadata.runbook_url {
+        links.push(Link {
+            href: runbook_url.clone(),
+            text: "Runbook".to_string(),
+        });
+    }
+    links.push(Link {
+        href: trigger.service_quota_url.clone(),
+        text: "Service Quota".to_string(),
+    });
+
+    links
+}
+
+#[async_trait]
+impl Deliver for Client {
+    type Error = ClientError;
+
+    // in digest mode the alerts are only sent as digests, once reconciled, but the alerts sent
+    // individually before switching to digests are still resolved individually
+    async fn trigger(&self, trigger: &Trigger) -> Result<bool, ClientError> {
+        if self.digest.is_some() {
+            return Ok(false);
+        }
+
+        match self.routing_key(trigger.alert.team.as_deref()) {
+            Some(routing_key) => {
+                self.send(
+                    &routing_key,
+                    &self.trigger_action(true),
+                    &trigger.dedup_key,
+                    Some(self.payload(trigger)?),
+                    links(trigger),
+                )
+                .await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn resolve(&self, resolve: &Resolve) -> Result<bool, ClientError> {
+        if resolve.alert.unsent {
+            return Ok(false);
+        }
+
+        match self.routing_key(resolve.alert.team.as_deref()) {
+            Some(routing_key) => {
+                self.send(
+                    &routing_key,
+                    &self.trigger_action(false),
+                    &resolve.dedup_key,
+                    None,
+                    Vec::new(),
+                )
+                .await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[async_trait]
+impl Notify for Client {
+    async fn notify(
+        &self,
+        quotas: &[Box<dyn Quota>],
+        scope: &Scope,
+        maintenance: &Maintenance,
+    ) -> Result<DeliveryReport, Box<dyn Error>> {
+        let mut state = AlertState::load(&self.state_path)?;
+        let mut report = DeliveryReport::new("pagerduty");
+
+        let breaching = reconcile::reconcile(
+            &self.policy,
+            quotas,
+            scope,
+            maintenance,
+            &mut state,
+            self,
+            &mut report,
+        )
+        .await;
+        match self.digest {
+            Some(digest) => {
+                self.send_digests(digest, &breaching, &mut state, &mut report)
+                    .await
+            }
+            None => {
+                self.resolve_digests(&BTreeMap::new(), &mut state, &mut report)
+                    .await
             }
         }
+        state.store(&self.state_path)?;
 
-        Ok(())
+        Ok(report)
     }
 }
 
-// The url format for the a service quota in the AWS console
-// example: https://us-east-1.console.aws.amazon.com/servicequotas/home/services/ec2/quotas/L-85EED4F7
-async fn service_quota_url(quota: &dyn Quota) -> String {
-    format!(
-        "https://{}.console.aws.amazon.com/servicequotas/home/services/{}/quotas/{}",
-        quota.region().await,
-        quota.service_code().await,
-        quota.quota_code().await,
-    )
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_retryable() {
+        assert!(retryable(429));
+        assert!(retryable(500));
+        assert!(retryable(503));
+        assert!(!retryable(400));
+        assert!(!retryable(403));
+    }
+
+    #[test]
+    fn test_breaker() {
+        let breaker = Breaker::default();
+        for _ in 0..MAX_CONSECUTIVE_FAILURES - 1 {
+            breaker.record(503);
+        }
+        assert!(!breaker.is_open());
+
+        // an accepted event resets the count
+        breaker.record(202);
+        for _ in 0..MAX_CONSECUTIVE_FAILURES - 1 {
+            breaker.record(429);
+        }
+        assert!(!breaker.is_open());
+
+        breaker.record(500);
+        assert!(breaker.is_open());
+    }
+
+    #[tokio::test]
+    async fn test_send_circuit_open() {
+        let policy = Policy {
+            thresholds: crate::thresholds::Thresholds::new(Default::default(), &[]).unwrap(),
+            snoozes: Default::default(),
+            ownership: Default::default(),
+            templates: Default::default(),
+            metadata: Default::default(),
+        };
+        let client = Client::new(Some("key"), &policy, PathBuf::new(), None).unwrap();
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            client.breaker.record(503);
+        }
+
+        // the remaining events fail without being sent
+        let mut report = DeliveryReport::new("pagerduty");
+        let result = client
+            .send("key", "resolve", "dedup-key", None, Vec::new())
+            .await
+            .map(|_| true);
+        assert!(!report.record(Action::Resolve, "dedup-key", result));
+        assert_eq!(
+            report.failed[0].1,
+            "CircuitOpen: not sent after 6 consecutive failures of the Events API"
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1, 0.0), Duration::from_millis(500));
+        assert_eq!(backoff(1, 1.0), Duration::from_secs(1));
+        assert_eq!(backoff(3, 0.5), Duration::from_secs(3));
+        assert_eq!(backoff(10, 1.0), MAX_BACKOFF);
+        assert_eq!(backoff(40, 0.0), MAX_BACKOFF / 2);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let backoff = Duration::from_secs(2);
+        assert_eq!(retry_delay(backoff, None), backoff);
+        assert_eq!(retry_delay(backoff, Some(Duration::from_secs(1))), backoff);
+        assert_eq!(
+            retry_delay(backoff, Some(Duration::from_secs(5))),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            retry_delay(backoff, Some(Duration::from_secs(3600))),
+            MAX_BACKOFF
+        );
+    }
 }
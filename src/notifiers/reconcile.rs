@@ -0,0 +1,633 @@
+// Reconcile decides, for every notifier alike, which alerts a scan triggers and resolves, from the
+// thresholds, snoozes, maintenance windows and the open alerts of the previous runs. Notifiers
+// only deliver the resulting events, in their own format.
+
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use crate::config::MaintenanceAction;
+use crate::maintenance::Maintenance;
+use crate::metadata::QuotaMetadata;
+use crate::notifiers::state::{Alert, AlertState, Scope};
+use crate::notifiers::{Action, DeliveryReport, Policy};
+use crate::quotas::{Quota, Utilization, GLOBAL_REGION};
+use crate::thresholds::{Severity, Threshold};
+
+// Trigger is a quota that is breaching, sent each run until it is resolved
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub dedup_key: String,
+    pub alert: Alert,
+    // the alert that was open before this run, None when the alert is new
+    pub previous: Option<Alert>,
+    pub arn: String,
+    pub utilization: Utilization,
+    pub threshold: Threshold,
+    pub service_quota_url: String,
+    pub metadata: QuotaMetadata,
+}
+
+impl Trigger {
+    // changed is true when the alert is new, or its severity or team changed since the
+    // previous run
+    pub fn changed(&self) -> bool {
+        match &self.previous {
+            Some(previous) => {
+                previous.severity != self.alert.severity || previous.team != self.alert.team
+            }
+            None => true,
+        }
+    }
+}
+
+// Resolve is an open alert that is no longer breaching, snoozed, has no usage data, or is no
+// longer observed
+#[derive(Debug, Clone)]
+pub struct Resolve {
+    pub dedup_key: String,
+    pub alert: Alert,
+    pub metadata: QuotaMetadata,
+}
+
+// Deliver sends the events of an alert, returning whether an event was sent
+#[async_trait]
+pub trait Deliver: Send + Sync {
+    type Error: Display;
+
+    async fn trigger(&self, trigger: &Trigger) -> Result<bool, Self::Error>;
+    async fn resolve(&self, resolve: &Resolve) -> Result<bool, Self::Error>;
+}
+
+pub async fn dedup_key(quota: &dyn Quota) -> String {
+    format!(
+        "{}-{}-{}",
+        quota.quota_code().await,
+        quota.region().await,
+        quota.account_id().await,
+    )
+}
+
+// reconcile triggers an alert for each quota whose breach has been sustained, and resolves the
+// open alerts that are below the resolve threshold, snoozed, have no usage data, or are no
+// longer observed within the scope of the scan. The state is only updated for the events that
+// were delivered, so that failed events are sent again in the next run. The dedup keys of the
+// quotas that are still breaching are returned, leaving out the quotas that are snoozed or held
+// by a maintenance window.
+pub async fn reconcile<D: Deliver>(
+    policy: &Policy,
+    quotas: &[Box<dyn Quota>],
+    scope: &Scope,
+    maintenance: &Maintenance,
+    state: &mut AlertState,
+    deliver: &D,
+    report: &mut DeliveryReport,
+) -> HashSet<String> {
+    let now = chrono::Utc::now().timestamp();
+    let mut observed = HashSet::new();
+    let mut breaching = HashSet::new();
+
+    for quota in quotas {
+        let dedup_key = dedup_key(&**quota).await;
+        observed.insert(dedup_key.clone());
+
+        if policy.snoozes.find(&**quota).await.is_some() {
+            state.clear(&dedup_key);
+            resolve(policy, &dedup_key, state, deliver, report).await;
+            continue;
+        }
+
+        // alerts held by a maintenance window are neither triggered nor resolved until it ends
+        let window = maintenance.action(&**quota).await;
+        if window == Some(MaintenanceAction::Hold) {
+            continue;
+        }
+
+        let utilization = match quota.utilization().await {
+            Ok(utilization) => utilization,
+            // the quota could not be evaluated, so whether it is still breaching is unknown
+            Err(err) if err.is_failure() => {
+                state.clear(&dedup_key);
+                continue;
+            }
+            Err(_) => {
+                state.clear(&dedup_key);
+                resolve(policy, &dedup_key, state, deliver, report).await;
+                continue;
+            }
+        };
+
+        let threshold = policy.thresholds.threshold(&**quota).await;
+        let severity = threshold.severity(&utilization);
+        let open = state.is_open(&dedup_key);
+
+        let severity = match (severity, open) {
+            (Some(severity), true) => severity,
+            // a new breach only triggers once it has been sustained
+            (Some(severity), false) => {
+                let pending = state.pend(&dedup_key, now);
+                if !threshold.sustained(pending.evaluations, now - pending.since) {
+                    continue;
+                }
+                severity
+            }
+            // an open alert is held until utilization drops below the resolve threshold
+            (None, true) if threshold.holds(&utilization) => continue,
+            (None, _) => {
+                state.clear(&dedup_key);
+                resolve(policy, &dedup_key, state, deliver, report).await;
+                continue;
+            }
+        };
+
+        let severity = match window {
+            Some(MaintenanceAction::Low) => Severity::Info,
+            _ => severity,
+        };
+        breaching.insert(dedup_key.clone());
+
+        let (quota_code, service_code, account_id, region) = (
+            quota.quota_code().await,
+            quota.service_code().await,
+            quota.account_id().await,
+            quota.region().await,
+        );
+        let ownership = &policy.ownership;
+        let previous = state.alerts.get(&dedup_key).cloned();
+        let team = match &previous {
+            // the last known owner is kept when the tags of the account could not be listed,
+            // rather than routing the alert elsewhere for a run
+            Some(previous)
+                if !ownership.is_owner_known(quota_code, service_code, account_id, region) =>
+            {
+                previous.team.clone()
+            }
+            _ => ownership
+                .owner(quota_code, service_code, account_id, region)
+                .map(|team| team.name.clone()),
+        };
+
+        let alert = Alert {
+            percentage: Some(utilization.percentage),
+            team,
+            ..Alert::new(&**quota, severity, now).await
+        };
+
+        // an escalated, or de-escalated, breach is triggered with the same dedup key, so that
+        // the notifiers update the open alert rather than opening another
+        let trigger = Trigger {
+            dedup_key: dedup_key.clone(),
+            alert: alert.clone(),
+            previous,
+            arn: quota.arn().await.to_string(),
+            utilization,
+            threshold,
+            service_quota_url: service_quota_url(&**quota).await,
+            metadata: policy
+                .metadata
+                .quota(quota_code, service_code, account_id, region),
+        };
+
+        // an alert whose owner changed is resolved for its previous team first, as the
+        // notifiers route it to the new team, where the previous incident would never resolve
+        if let Some(previous) = &trigger.previous {
+            if previous.team != trigger.alert.team {
+                let result = deliver
+                    .resolve(&Resolve {
+                        dedup_key: dedup_key.clone(),
+                        alert: previous.clone(),
+                        metadata: trigger.metadata.clone(),
+                    })
+                    .await;
+                if !report.record(Action::Resolve, &dedup_key, result) {
+                    continue;
+                }
+            }
+        }
+
+        let result = deliver.trigger(&trigger).await;
+        let unsent = !matches!(result, Ok(true));
+        if report.record(Action::Trigger, &dedup_key, result) {
+            state.open(&dedup_key, Alert { unsent, ..alert });
+        }
+    }
+
+    // a pending breach must be breaching in consecutive runs, so it starts over when the quota
+    // was not evaluated
+    state
+        .pending
+        .retain(|dedup_key, _| observed.contains(dedup_key));
+
+    // the windows are matched against the open alert, as there is no quota to match them against
+    let held = |alert: &Alert| {
+        maintenance.action_of(
+            &alert.quota_code,
+            &alert.service_code,
+            &alert.account_id,
+            &alert.region,
+        ) == Some(MaintenanceAction::Hold)
+    };
+    let unobserved = state
+        .alerts
+        .iter()
+        .filter(|(dedup_key, alert)| {
+            !observed.contains(*dedup_key) && scope.contains(alert) && !held(alert)
+        })
+        .map(|(dedup_key, _)| dedup_key.clone())
+        .collect::<Vec<_>>();
+
+    for dedup_key in unobserved {
+        resolve(policy, &dedup_key, state, deliver, report).await;
+    }
+
+    breaching
+}
+
+// resolve delivers the resolve of an open alert, and closes it once delivered
+async fn resolve<D: Deliver>(
+    policy: &Policy,
+    dedup_key: &str,
+    state: &mut AlertState,
+    deliver: &D,
+    report: &mut DeliveryReport,
+) {
+    let Some(alert) = state.alerts.get(dedup_key).cloned() else {
+        return;
+    };
+
+    let result = deliver
+        .resolve(&Resolve {
+            dedup_key: dedup_key.to_string(),
+            metadata: policy.metadata.quota(
+                &alert.quota_code,
+                &alert.service_code,
+                &alert.account_id,
+                &alert.region,
+            ),
+            alert,
+        })
+        .await;
+    if report.record(Action::Resolve, dedup_key, result) {
+        state.close(dedup_key);
+    }
+}
+
+// The url format for the a service quota in the AWS console
+// example: https://us-east-1.console.aws.amazon.com/servicequotas/home/services/ec2/quotas/L-85EED4F7
+async fn service_quota_url(quota: &dyn Quota) -> String {
+    console_url(
+        quota.region().await,
+        quota.service_code().await,
+        quota.quota_code().await,
+    )
+}
+
+// Global quotas use the console of the default region.
+pub fn console_url(region: &str, service_code: &str, quota_code: &str) -> String {
+    let host = match region {
+        GLOBAL_REGION => "console.aws.amazon.com".to_string(),
+        region => format!("{}.console.aws.amazon.com", region),
+    };
+
+    format!(
+        "https://{}/servicequotas/home/services/{}/quotas/{}",
+        host, service_code, quota_code,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{AccountTag, Config, Matcher, OwnershipRule, Team};
+    use crate::filter::Filter;
+    use crate::maintenance::Maintenance;
+    use crate::metadata::Metadata;
+    use crate::notifiers::templates::Templates;
+    use crate::notifiers::Event;
+    use crate::ownership::Ownership;
+    use crate::quotas::{Source, TestQuota, UtilizationError};
+    use crate::snoozes::Snoozes;
+    use crate::thresholds::Thresholds;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    // FailingDeliver fails the events of the dedup keys it is given, and delivers the others
+    struct FailingDeliver {
+        failing: Vec<String>,
+    }
+
+    #[async_trait]
+    impl Deliver for FailingDeliver {
+        type Error = String;
+
+        async fn trigger(&self, trigger: &Trigger) -> Result<bool, String> {
+            match self.failing.contains(&trigger.dedup_key) {
+                true => Err("rate limited".to_string()),
+                false => Ok(true),
+            }
+        }
+
+        async fn resolve(&self, resolve: &Resolve) -> Result<bool, String> {
+            match self.failing.contains(&resolve.dedup_key) {
+                true => Err("rate limited".to_string()),
+                false => Ok(true),
+            }
+        }
+    }
+
+    fn quota(quota_code: &str, percentage: u8) -> Box<dyn Quota> {
+        Box::new(TestQuota::new(
+            quota_code,
+            "us-east-1",
+            Ok(Utilization {
+                percentage,
+                source: Source::CloudWatch,
+                usage: None,
+                limit: None,
+            }),
+        ))
+    }
+
+    // RecordingDeliver delivers every event, recording its action and team
+    #[derive(Default)]
+    struct RecordingDeliver {
+        events: Mutex<Vec<(Action, Option<String>)>>,
+    }
+
+    #[async_trait]
+    impl Deliver for RecordingDeliver {
+        type Error = String;
+
+        async fn trigger(&self, trigger: &Trigger) -> Result<bool, String> {
+            let event = (Action::Trigger, trigger.alert.team.clone());
+            self.events.lock().unwrap().push(event);
+            Ok(true)
+        }
+
+        async fn resolve(&self, resolve: &Resolve) -> Result<bool, String> {
+            let event = (Action::Resolve, resolve.alert.team.clone());
+            self.events.lock().unwrap().push(event);
+            Ok(true)
+        }
+    }
+
+    fn policy(ownership: Ownership) -> Policy {
+        Policy {
+            thresholds: Thresholds::new(
+                Threshold {
+                    percentage: 80,
+                    ..Default::default()
+                },
+                &[],
+            )
+            .unwrap(),
+            snoozes: Snoozes::default(),
+            ownership,
+            templates: Templates::default(),
+            metadata: Metadata::default(),
+        }
+    }
+
+    // record reconciles the quotas, returning the events delivered
+    async fn record(
+        policy: &Policy,
+        quotas: &[Box<dyn Quota>],
+        maintenance: &Maintenance,
+        state: &mut AlertState,
+    ) -> Vec<(Action, Option<String>)> {
+        let deliver = RecordingDeliver::default();
+        let mut report = DeliveryReport::new("test");
+
+        reconcile(
+            policy,
+            quotas,
+            &scope(),
+            maintenance,
+            state,
+            &deliver,
+            &mut report,
+        )
+        .await;
+
+        deliver.events.into_inner().unwrap()
+    }
+
+    // hold is an open maintenance window holding the alerts of the quota
+    fn hold(quota_code: &str) -> Maintenance {
+        let config = Config::parse(&format!(
+            r#"
+            [[maintenance]]
+            quota_code = "{}"
+            start = 2026-11-01T22:00:00Z
+            end = 2026-11-02T02:00:00Z
+            "#,
+            quota_code
+        ))
+        .unwrap();
+
+        Maintenance::new(&config.maintenance, "2026-11-01T23:00:00Z".parse().unwrap()).unwrap()
+    }
+
+    fn scope() -> Scope {
+        Scope {
+            account_ids: vec!["123456789012".to_string()],
+            regions: vec!["us-east-1".to_string()],
+            filter: Filter::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_failed_events() {
+        let policy = policy(Ownership::default());
+        let scope = scope();
+        let maintenance = Maintenance::new(&[], chrono::Utc::now()).unwrap();
+
+        // A and B are breaching, C and D were open and are no longer breaching
+        let quotas = vec![
+            quota("L-A", 90),
+            quota("L-B", 90),
+            quota("L-C", 10),
+            Box::new(TestQuota::new(
+                "L-D",
+                "us-east-1",
+                Err(UtilizationError::MissingMetricData),
+            )) as Box<dyn Quota>,
+        ];
+        let mut state = AlertState::default();
+        for quota in &quotas[2..] {
+            let alert = Alert::new(&**quota, Severity::Warning, 0).await;
+            state.open(&dedup_key(&**quota).await, alert);
+        }
+        let previous = state.clone();
+
+        let deliver = FailingDeliver {
+            failing: vec![
+                "L-A-us-east-1-123456789012".to_string(),
+                "L-C-us-east-1-123456789012".to_string(),
+            ],
+        };
+        let mut report = DeliveryReport::new("test");
+        reconcile(
+            &policy,
+            &quotas,
+            &scope,
+            &maintenance,
+            &mut state,
+            &deliver,
+            &mut report,
+        )
+        .await;
+
+        let keys = |events: Vec<&Event>| {
+            events
+                .into_iter()
+                .map(|event| (event.action, event.dedup_key.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        // the events after a failure are still delivered
+        assert_eq!(
+            keys(report.failed.iter().map(|(event, _)| event).collect()),
+            vec![
+                (Action::Trigger, "L-A-us-east-1-123456789012".to_string()),
+                (Action::Resolve, "L-C-us-east-1-123456789012".to_string()),
+            ]
+        );
+        assert_eq!(
+            keys(report.accepted.iter().collect()),
+            vec![
+                (Action::Trigger, "L-B-us-east-1-123456789012".to_string()),
+                (Action::Resolve, "L-D-us-east-1-123456789012".to_string()),
+            ]
+        );
+
+        // the failed events leave the state as it was, so they are sent again in the next run
+        assert!(!state.is_open("L-A-us-east-1-123456789012"));
+        assert!(state.is_open("L-B-us-east-1-123456789012"));
+        assert_eq!(
+            state.alerts.get("L-C-us-east-1-123456789012"),
+            previous.alerts.get("L-C-us-east-1-123456789012")
+        );
+        assert!(!state.is_open("L-D-us-east-1-123456789012"));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_team_change() {
+        let team = |name: &str| Team {
+            name: name.to_string(),
+            routing_key: Some(format!("{}-key", name)),
+            routing_key_env: None,
+            slack_channel: None,
+        };
+        // the account is owned by payments when tagged so, and by platform otherwise
+        let ownership = |tags: Option<&str>| {
+            let mut ownership = Ownership::new(
+                &[team("platform"), team("payments")],
+                &[
+                    OwnershipRule {
+                        matcher: Matcher::default(),
+                        account_tag: None,
+                        team: "platform".to_string(),
+                    },
+                    OwnershipRule {
+                        matcher: Matcher::default(),
+                        account_tag: Some(AccountTag {
+                            key: "team".to_string(),
+                            value: "payments".to_string(),
+                        }),
+                        team: "payments".to_string(),
+                    },
+                ],
+            )
+            .unwrap();
+            match tags {
+                Some(team) => ownership.set_account_tags(
+                    "123456789012",
+                    HashMap::from([("team".to_string(), team.to_string())]),
+                ),
+                None => ownership.set_unknown_tags("123456789012"),
+            }
+            ownership
+        };
+        let quotas = vec![quota("L-A", 90)];
+        let dedup_key = dedup_key(&*quotas[0]).await;
+        let maintenance = Maintenance::new(&[], chrono::Utc::now()).unwrap();
+
+        let mut state = AlertState::default();
+        let alert = Alert {
+            team: Some("platform".to_string()),
+            ..Alert::new(&*quotas[0], Severity::Warning, 0).await
+        };
+        state.open(&dedup_key, alert);
+
+        // the tags of the account could not be listed, so the alert stays with its last owner
+        assert_eq!(
+            record(&policy(ownership(None)), &quotas, &maintenance, &mut state).await,
+            vec![(Action::Trigger, Some("platform".to_string()))]
+        );
+
+        // the account is now owned by payments, so the alert of platform is resolved first
+        assert_eq!(
+            record(
+                &policy(ownership(Some("payments"))),
+                &quotas,
+                &maintenance,
+                &mut state
+            )
+            .await,
+            vec![
+                (Action::Resolve, Some("platform".to_string())),
+                (Action::Trigger, Some("payments".to_string())),
+            ]
+        );
+        assert_eq!(state.alerts[&dedup_key].team, Some("payments".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_held_unobserved() {
+        let maintenance = hold("L-A");
+
+        // A and B were open and are no longer observed, but A is held by the window
+        let mut state = AlertState::default();
+        for quota in [quota("L-A", 90), quota("L-B", 90)] {
+            let alert = Alert::new(&*quota, Severity::Warning, 0).await;
+            state.open(&dedup_key(&*quota).await, alert);
+        }
+
+        assert_eq!(
+            record(&policy(Ownership::default()), &[], &maintenance, &mut state).await,
+            vec![(Action::Resolve, None)]
+        );
+        assert!(state.is_open("L-A-us-east-1-123456789012"));
+        assert!(!state.is_open("L-B-us-east-1-123456789012"));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_breaching() {
+        let maintenance = hold("L-A");
+
+        // A and B are breaching and open, but A is held by the window, and C is not breaching
+        let quotas = vec![quota("L-A", 90), quota("L-B", 90), quota("L-C", 10)];
+        let mut state = AlertState::default();
+        for quota in &quotas[..2] {
+            let alert = Alert::new(&**quota, Severity::Warning, 0).await;
+            state.open(&dedup_key(&**quota).await, alert);
+        }
+
+        let breaching = reconcile(
+            &policy(Ownership::default()),
+            &quotas,
+            &scope(),
+            &maintenance,
+            &mut state,
+            &RecordingDeliver::default(),
+            &mut DeliveryReport::new("test"),
+        )
+        .await;
+
+        assert_eq!(
+            breaching,
+            HashSet::from(["L-B-us-east-1-123456789012".to_string()])
+        );
+    }
+}
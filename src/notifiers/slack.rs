@@ -0,0 +1,192 @@
+// Slack posts a message to the channel of the team owning a quota when its alert opens, changes
+// severity or resolves. Unlike PagerDuty, Slack has no dedup keys, so an alert that is still
+// breaching at the same severity is not posted again.
+
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, InvalidHeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+
+use crate::maintenance::Maintenance;
+use crate::notifiers::reconcile::{self, Deliver, Resolve, Trigger};
+use crate::notifiers::state::{AlertState, Scope};
+use crate::notifiers::templates::{Context, TemplateError};
+use crate::notifiers::{DeliveryReport, Notify, Policy};
+use crate::quotas::Quota;
+
+const POST_MESSAGE_URL: &str = "https://slack.com/api/chat.postMessage";
+
+#[derive(Debug)]
+pub enum ClientError {
+    ReqwestError(reqwest::Error),
+    InvalidHeaderValue(InvalidHeaderValue),
+    TemplateError(TemplateError),
+    SlackApiError(String),
+}
+
+impl Error for ClientError {}
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::ReqwestError(e) => write!(f, "RequestError: {}", e),
+            Self::InvalidHeaderValue(e) => write!(f, "InvalidHeaderValue: {}", e),
+            Self::TemplateError(e) => write!(f, "TemplateError: {}", e),
+            Self::SlackApiError(e) => write!(f, "Slack API Error: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::ReqwestError(err)
+    }
+}
+
+impl From<InvalidHeaderValue> for ClientError {
+    fn from(err: InvalidHeaderValue) -> Self {
+        Self::InvalidHeaderValue(err)
+    }
+}
+
+impl From<TemplateError> for ClientError {
+    fn from(err: TemplateError) -> Self {
+        Self::TemplateError(err)
+    }
+}
+
+pub struct Client {
+    client: reqwest::Client,
+    // the channel of the alerts of quotas without an owning team with a channel
+    channel: Option<String>,
+    policy: Policy,
+    // the file the open alerts are persisted to between runs
+    state_path: PathBuf,
+}
+
+#[derive(Serialize, Debug)]
+struct PostMessage {
+    channel: String,
+    text: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PostMessageResponse {
+    ok: bool,
+    error: Option<String>,
+}
+
+impl Client {
+    pub fn new(
+        token: &str,
+        channel: Option<&str>,
+        policy: &Policy,
+        state_path: PathBuf,
+    ) -> Result<Client, ClientError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json")?);
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        );
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            channel: channel.map(|c| c.to_string()),
+            policy: policy.clone(),
+            state_path,
+        })
+    }
+
+    // channel is the channel of the team owning the alert, or the default channel
+    fn channel(&self, team: Option<&str>) -> Option<String> {
+        team.and_then(|team| self.policy.ownership.team(team))
+            .and_then(|team| team.slack_channel.clone())
+            .or_else(|| self.channel.clone())
+    }
+
+    async fn post(&self, channel: &str, text: String) -> Result<(), ClientError> {
+        let message = PostMessage {
+            channel: channel.to_string(),
+            text,
+        };
+
+        let response = self
+            .client
+            .post(POST_MESSAGE_URL)
+            .json(&message)
+            .send()
+            .await?
+            .json::<PostMessageResponse>()
+            .await?;
+
+        match response.ok {
+            true => Ok(()),
+            false => Err(ClientError::SlackApiError(
+                response.error.unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl Deliver for Client {
+    type Error = ClientError;
+
+    async fn trigger(&self, trigger: &Trigger) -> Result<bool, ClientError> {
+        let Some(channel) = self.channel(trigger.alert.team.as_deref()) else {
+            return Ok(false);
+        };
+        if !trigger.changed() {
+            return Ok(false);
+        }
+
+        let text = self.policy.templates.chat(&Context::from(trigger))?;
+
+        self.post(&channel, text).await?;
+        Ok(true)
+    }
+
+    async fn resolve(&self, resolve: &Resolve) -> Result<bool, ClientError> {
+        let Some(channel) = self.channel(resolve.alert.team.as_deref()) else {
+            return Ok(false);
+        };
+
+        let text = self.policy.templates.chat(&Context::from(resolve))?;
+
+        self.post(&channel, text).await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl Notify for Client {
+    async fn notify(
+        &self,
+        quotas: &[Box<dyn Quota>],
+        scope: &Scope,
+        maintenance: &Maintenance,
+    ) -> Result<DeliveryReport, Box<dyn Error>> {
+        let mut state = AlertState::load(&self.state_path)?;
+        let mut report = DeliveryReport::new("slack");
+
+        reconcile::reconcile(
+            &self.policy,
+            quotas,
+            scope,
+            maintenance,
+            &mut state,
+            self,
+            &mut report,
+        )
+        .await;
+        state.store(&self.state_path)?;
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,434 @@
+// AlertState persists the alerts that are open, per dedup key, so that each run can resolve the
+// alerts that are no longer breaching, or no longer observed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Path, PathBuf};
+
+use crate::config::Digest;
+use crate::filter::Filter;
+use crate::quotas::Quota;
+use crate::thresholds::Severity;
+
+// the version of the state file format, state files of other versions are rejected rather than
+// overwritten, so that open alerts are never forgotten
+const STATE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum StateError {
+    IoError(std::io::Error),
+    SerdeError(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl Error for StateError {}
+impl Display for StateError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::IoError(e) => write!(f, "IoError: {}", e),
+            Self::SerdeError(e) => write!(f, "SerdeError: {}", e),
+            Self::UnsupportedVersion(v) => {
+                write!(f, "UnsupportedVersion: {}, expected {}", v, STATE_VERSION)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for StateError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for StateError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::SerdeError(err)
+    }
+}
+
+// Alert is the quota an open alert was triggered for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    pub account_id: String,
+    pub region: String,
+    pub service_code: String,
+    pub quota_code: String,
+    pub name: String,
+    // the severity the alert was last triggered at, unknown for alerts opened before severities
+    #[serde(default)]
+    pub severity: Option<Severity>,
+    // the team owning the quota, which the alert is routed to
+    #[serde(default)]
+    pub team: Option<String>,
+    // the utilization the alert was last triggered at
+    #[serde(default)]
+    pub percentage: Option<u8>,
+    // unsent alerts were never sent as their own event, e.g. only in a digest, so there is
+    // nothing to resolve for them individually
+    #[serde(default)]
+    pub unsent: bool,
+    // unix timestamps, in seconds, of when the alert was first and last triggered
+    pub opened_at: i64,
+    pub triggered_at: i64,
+}
+
+impl Alert {
+    pub async fn new(quota: &dyn Quota, severity: Severity, now: i64) -> Self {
+        Self {
+            account_id: quota.account_id().await.to_string(),
+            region: quota.region().await.to_string(),
+            service_code: quota.service_code().await.to_string(),
+            quota_code: quota.quota_code().await.to_string(),
+            name: quota.name().await.to_string(),
+            severity: Some(severity),
+            team: None,
+            percentage: None,
+            unsent: false,
+            opened_at: now,
+            triggered_at: now,
+        }
+    }
+}
+
+// OpenDigest is the alerts of a digest, grouped by account, or service, and owning team
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenDigest {
+    pub team: Option<String>,
+    pub dedup_keys: Vec<String>,
+}
+
+// Pending is a breach that has not lasted long enough to trigger an alert yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pending {
+    // unix timestamp, in seconds, of the first evaluation the quota was breaching in
+    pub since: i64,
+    // the number of consecutive evaluations the quota was breaching in
+    pub evaluations: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertState {
+    pub version: u32,
+    pub alerts: BTreeMap<String, Alert>,
+    #[serde(default)]
+    pub pending: BTreeMap<String, Pending>,
+    // the open digests, per digest dedup key
+    #[serde(default)]
+    pub digests: BTreeMap<String, OpenDigest>,
+}
+
+impl Default for AlertState {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            alerts: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            digests: BTreeMap::new(),
+        }
+    }
+}
+
+impl AlertState {
+    // load reads the state file, a missing file has no open alerts
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let state = serde_json::from_str::<Self>(&contents)?;
+        if state.version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(state.version));
+        }
+
+        Ok(state)
+    }
+
+    pub fn store(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // write to a temporary file first, so that an interrupted run never corrupts the state
+        let tmp_path = path.with_extension(format!("json.{}", std::process::id()));
+
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn is_open(&self, dedup_key: &str) -> bool {
+        self.alerts.contains_key(dedup_key)
+    }
+
+    // pend records another evaluation a quota was breaching in, without an alert open
+    pub fn pend(&mut self, dedup_key: &str, now: i64) -> Pending {
+        let pending = self
+            .pending
+            .entry(dedup_key.to_string())
+            .or_insert(Pending {
+                since: now,
+                evaluations: 0,
+            });
+        pending.evaluations += 1;
+
+        *pending
+    }
+
+    // clear forgets a pending breach, once the quota is no longer breaching or alerted on
+    pub fn clear(&mut self, dedup_key: &str) {
+        self.pending.remove(dedup_key);
+    }
+
+    // open records a triggered alert, keeping when it was first opened
+    pub fn open(&mut self, dedup_key: &str, alert: Alert) {
+        self.clear(dedup_key);
+
+        match self.alerts.get_mut(dedup_key) {
+            Some(open) => {
+                // an alert sent once stays sent until it is resolved, or moves to another team
+                open.unsent = match open.team == alert.team {
+                    true => open.unsent && alert.unsent,
+                    false => alert.unsent,
+                };
+                open.severity = alert.severity;
+                open.percentage = alert.percentage;
+                open.team = alert.team;
+                open.triggered_at = alert.triggered_at;
+            }
+            None => {
+                self.alerts.insert(dedup_key.to_string(), alert);
+            }
+        }
+    }
+
+    pub fn close(&mut self, dedup_key: &str) {
+        self.alerts.remove(dedup_key);
+    }
+
+    // groups is the open alerts of each digest, per digest dedup key
+    pub fn groups(&self, digest: Digest) -> BTreeMap<String, OpenDigest> {
+        let mut groups: BTreeMap<String, OpenDigest> = BTreeMap::new();
+
+        for (dedup_key, alert) in &self.alerts {
+            let group = groups.entry(digest_key(digest, alert)).or_default();
+            group.team = alert.team.clone();
+            group.dedup_keys.push(dedup_key.clone());
+        }
+
+        groups
+    }
+}
+
+// digest_key is the dedup key of the digest an alert is grouped into, it only depends on the
+// account, service and owning team, so that it is stable across runs. The team is a dimension
+// of the groups, as each team's digest is routed to the team: an alert whose owner changes moves
+// to the digest of its new team, and leaves the digest of its previous team.
+pub fn digest_key(digest: Digest, alert: &Alert) -> String {
+    let mut key = format!("digest-{}", alert.account_id);
+
+    if digest == Digest::Service {
+        key = format!("{}-{}", key, alert.service_code);
+    }
+
+    match &alert.team {
+        Some(team) => format!("{}-{}", key, team),
+        None => key,
+    }
+}
+
+// default_state_path is $XDG_STATE_HOME/service-quotas/alerts.json, or
+// ~/.local/state/service-quotas/alerts.json
+pub fn default_state_path() -> Option<PathBuf> {
+    let state_home = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+
+    Some(state_home.join("service-quotas").join("alerts.json"))
+}
+
+// Scope is what a run scanned completely. Open alerts that were not observed are only resolved
+// when they are in scope, so that a partial scan, or a scan of other services, does not resolve
+// them.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub account_ids: Vec<String>,
+    pub regions: Vec<String>,
+    pub filter: Filter,
+}
+
+impl Scope {
+    pub fn contains(&self, alert: &Alert) -> bool {
+        self.account_ids.contains(&alert.account_id)
+            && self.regions.contains(&alert.region)
+            && self.filter.matches_service(&alert.service_code)
+            && match &self.filter.quotas {
+                Some(quotas) => quotas.contains(&alert.quota_code),
+                None => true,
+            }
+            && match &self.filter.name {
+                Some(name) => name.is_match(&alert.name),
+                None => true,
+            }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn alert(region: &str, service_code: &str, now: i64) -> Alert {
+        Alert {
+            severity: Some(Severity::Warning),
+            team: None,
+            percentage: Some(80),
+            unsent: false,
+            account_id: "123456789012".to_string(),
+            region: region.to_string(),
+            service_code: service_code.to_string(),
+            quota_code: "L-0263D0A3".to_string(),
+            name: "EC2-VPC Elastic IPs".to_string(),
+            opened_at: now,
+            triggered_at: now,
+        }
+    }
+
+    #[test]
+    fn test_open_and_close() {
+        let mut state = AlertState::default();
+
+        state.open("key", alert("us-east-1", "ec2", 1));
+        state.open(
+            "key",
+            Alert {
+                severity: Some(Severity::Critical),
+                ..alert("us-east-1", "ec2", 2)
+            },
+        );
+        assert!(state.is_open("key"));
+        assert_eq!(state.alerts["key"].opened_at, 1);
+        assert_eq!(state.alerts["key"].triggered_at, 2);
+        assert_eq!(state.alerts["key"].severity, Some(Severity::Critical));
+
+        state.close("key");
+        assert!(!state.is_open("key"));
+
+        let unsent = |now| Alert {
+            unsent: true,
+            ..alert("us-east-1", "ec2", now)
+        };
+        state.open("key", unsent(1));
+        assert!(state.alerts["key"].unsent);
+        state.open("key", alert("us-east-1", "ec2", 2));
+        state.open("key", unsent(3));
+        assert!(!state.alerts["key"].unsent);
+    }
+
+    #[test]
+    fn test_pend() {
+        let mut state = AlertState::default();
+
+        state.pend("key", 1);
+        assert_eq!(
+            state.pend("key", 2),
+            Pending {
+                since: 1,
+                evaluations: 2
+            }
+        );
+
+        state.open("key", alert("us-east-1", "ec2", 3));
+        assert!(state.pending.is_empty());
+
+        state.pend("other", 1);
+        state.clear("other");
+        assert!(state.pending.is_empty());
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut state = AlertState::default();
+        state.open("ec2-us-east-1", alert("us-east-1", "ec2", 1));
+        state.open("ec2-eu-west-1", alert("eu-west-1", "ec2", 1));
+        state.open(
+            "vpc-us-east-1",
+            Alert {
+                team: Some("network".to_string()),
+                ..alert("us-east-1", "vpc", 1)
+            },
+        );
+
+        let keys = |groups: BTreeMap<String, OpenDigest>| {
+            groups
+                .into_iter()
+                .map(|(key, group)| (key, group.dedup_keys))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            keys(state.groups(Digest::Account)),
+            vec![
+                (
+                    "digest-123456789012".to_string(),
+                    vec!["ec2-eu-west-1".to_string(), "ec2-us-east-1".to_string()]
+                ),
+                (
+                    "digest-123456789012-network".to_string(),
+                    vec!["vpc-us-east-1".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(
+            keys(state.groups(Digest::Service)),
+            vec![
+                (
+                    "digest-123456789012-ec2".to_string(),
+                    vec!["ec2-eu-west-1".to_string(), "ec2-us-east-1".to_string()]
+                ),
+                (
+                    "digest-123456789012-vpc-network".to_string(),
+                    vec!["vpc-us-east-1".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let path = std::env::temp_dir()
+            .join(format!("service-quotas-test-state-{}", std::process::id()))
+            .join("alerts.json");
+
+        assert_eq!(AlertState::load(&path).unwrap(), AlertState::default());
+
+        let mut state = AlertState::default();
+        state.open("key", alert("us-east-1", "ec2", 1));
+        state.store(&path).unwrap();
+        assert_eq!(AlertState::load(&path).unwrap(), state);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_scope_contains() {
+        let scope = Scope {
+            account_ids: vec!["123456789012".to_string()],
+            regions: vec!["us-east-1".to_string()],
+            filter: Filter {
+                services: Some(vec!["ec2".to_string()]),
+                ..Default::default()
+            },
+        };
+
+        assert!(scope.contains(&alert("us-east-1", "ec2", 1)));
+        assert!(!scope.contains(&alert("eu-west-1", "ec2", 1)));
+        assert!(!scope.contains(&alert("us-east-1", "vpc", 1)));
+        assert!(!Scope::default().contains(&alert("us-east-1", "ec2", 1)));
+    }
+}
@@ -0,0 +1,390 @@
+// Templates render the text of the notifications from Handlebars templates over the context of
+// each alert, so that teams can add runbook links, environments and the like to their alerts.
+// Templates that are not set in the config file render the default text.
+
+use handlebars::{no_escape, Handlebars};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::config::TemplatesConfig;
+use crate::metadata::QuotaMetadata;
+use crate::notifiers::reconcile::{console_url, Resolve, Trigger};
+use crate::notifiers::state::Alert;
+
+const SUMMARY: &str = "Service Quota Utilization {{utilization_percentage}}%: {{quota_code}} - {{quota_name}} in {{account_id}}{{#if account_alias}} ({{account_alias}}){{/if}} - {{region}}";
+const DIGEST_SUMMARY: &str = "Service Quota Utilization: {{count}} quotas breached in {{account_id}}{{#if account_alias}} ({{account_alias}}){{/if}}{{#if service}} - {{service}}{{/if}}";
+const SOURCE: &str = "https://github.com/robpickerill/service-quotas";
+const CHAT: &str = "[{{severity}}] Service Quota Utilization {{utilization_percentage}}%: {{quota_code}} - {{quota_name}} in {{account_id}}{{#if account_alias}} ({{account_alias}}){{/if}} - {{region}}\n{{service_quota_url}}{{#if runbook_url}}\nRunbook: {{runbook_url}}{{/if}}";
+const CHAT_RESOLVED: &str = "[resolved] Service Quota Utilization: {{quota_code}} - {{quota_name}} in {{account_id}}{{#if account_alias}} ({{account_alias}}){{/if}} - {{region}}";
+
+// the names of the built-in custom details of an alert, which custom details cannot replace
+const RESERVED_DETAILS: [&str; 19] = [
+    "arn",
+    "account_id",
+    "service",
+    "region",
+    "quota_name",
+    "quota_code",
+    "utilization_percentage",
+    "utilization_source",
+    "usage",
+    "limit",
+    "threshold",
+    "headroom_threshold",
+    "error_threshold",
+    "critical_threshold",
+    "service_quota_url",
+    "team",
+    "runbook_url",
+    "account_alias",
+    "environment",
+];
+
+#[derive(Debug)]
+pub enum TemplateError {
+    InvalidTemplate(String, Box<handlebars::TemplateError>),
+    ReservedDetail(String),
+    RenderError(Box<handlebars::RenderError>),
+}
+
+impl Error for TemplateError {}
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::InvalidTemplate(name, e) => write!(f, "InvalidTemplate: {}: {}", name, e),
+            Self::ReservedDetail(name) => write!(f, "ReservedDetail: {}", name),
+            Self::RenderError(e) => write!(f, "RenderError: {}", e),
+        }
+    }
+}
+
+impl From<handlebars::RenderError> for TemplateError {
+    fn from(err: handlebars::RenderError) -> Self {
+        Self::RenderError(Box::new(err))
+    }
+}
+
+// Context is what the templates of an alert are rendered over. Fields that are unknown, e.g. the
+// utilization of an alert opened before it was recorded, render empty.
+#[derive(Debug, Clone, Serialize)]
+pub struct Context {
+    // triggered, or resolved
+    pub status: String,
+    pub dedup_key: String,
+    pub account_id: String,
+    pub region: String,
+    pub service_code: String,
+    pub quota_code: String,
+    pub quota_name: String,
+    pub arn: Option<String>,
+    pub severity: Option<String>,
+    pub team: Option<String>,
+    pub utilization_percentage: Option<u8>,
+    pub utilization_source: Option<String>,
+    pub usage: Option<f64>,
+    pub limit: Option<f64>,
+    pub threshold: Option<u8>,
+    pub service_quota_url: String,
+    // the runbook_url, account_alias and environment of the quota
+    #[serde(flatten)]
+    pub metadata: QuotaMetadata,
+}
+
+impl Context {
+    pub fn new(status: &str, dedup_key: &str, alert: &Alert, metadata: &QuotaMetadata) -> Self {
+        Self {
+            status: status.to_string(),
+            dedup_key: dedup_key.to_string(),
+            account_id: alert.account_id.clone(),
+            region: alert.region.clone(),
+            service_code: alert.service_code.clone(),
+            quota_code: alert.quota_code.clone(),
+            quota_name: alert.name.clone(),
+            arn: None,
+            severity: alert.severity.map(|s| s.to_string()),
+            team: alert.team.clone(),
+            utilization_percentage: alert.percentage,
+            utilization_source: None,
+            usage: None,
+            limit: None,
+            threshold: None,
+            service_quota_url: console_url(&alert.region, &alert.service_code, &alert.quota_code),
+            metadata: metadata.clone(),
+        }
+    }
+}
+
+impl From<&Trigger> for Context {
+    fn from(trigger: &Trigger) -> Self {
+        Self {
+            arn: Some(trigger.arn.clone()),
+            utilization_percentage: Some(trigger.utilization.percentage),
+            utilization_source: Some(trigger.utilization.source.to_string()),
+            usage: trigger.utilization.usage,
+            limit: trigger.utilization.limit,
+            threshold: Some(trigger.threshold.percentage),
+            ..Self::new(
+                "triggered",
+                &trigger.dedup_key,
+                &trigger.alert,
+                &trigger.metadata,
+            )
+        }
+    }
+}
+
+impl From<&Resolve> for Context {
+    fn from(resolve: &Resolve) -> Self {
+        Self::new(
+            "resolved",
+            &resolve.dedup_key,
+            &resolve.alert,
+            &resolve.metadata,
+        )
+    }
+}
+
+// DigestContext is what the summary of a digest is rendered over, with the context of each of
+// its quotas
+#[derive(Debug, Clone, Serialize)]
+pub struct DigestContext {
+    pub account_id: String,
+    pub account_alias: Option<String>,
+    pub environment: Option<String>,
+    pub service: Option<String>,
+    pub team: Option<String>,
+    pub severity: String,
+    pub count: usize,
+    pub quotas: Vec<Context>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Templates {
+    registry: Handlebars<'static>,
+    // the names of the custom details added to the details of each alert
+    custom_details: Vec<String>,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self::new(&TemplatesConfig::default()).expect("the default templates are valid")
+    }
+}
+
+impl Templates {
+    pub fn new(config: &TemplatesConfig) -> Result<Self, TemplateError> {
+        let mut registry = Handlebars::new();
+        // notifications are plain text, not HTML
+        registry.register_escape_fn(no_escape);
+
+        let templates = [
+            ("summary", &config.summary, SUMMARY),
+            ("digest_summary", &config.digest_summary, DIGEST_SUMMARY),
+            ("source", &config.source, SOURCE),
+            ("chat", &config.chat, CHAT),
+            ("chat_resolved", &config.chat_resolved, CHAT_RESOLVED),
+        ];
+        for (name, template, default) in templates {
+            let template = template.as_deref().unwrap_or(default);
+            register(&mut registry, name, template)?;
+        }
+
+        for (name, template) in &config.custom_details {
+            if RESERVED_DETAILS.contains(&name.as_str()) {
+                return Err(TemplateError::ReservedDetail(name.clone()));
+            }
+            register(&mut registry, &custom_detail(name), template)?;
+        }
+
+        Ok(Self {
+            registry,
+            custom_details: config.custom_details.keys().cloned().collect(),
+        })
+    }
+
+    pub fn summary(&self, context: &Context) -> Result<String, TemplateError> {
+        Ok(self.registry.render("summary", context)?)
+    }
+
+    pub fn digest_summary(&self, context: &DigestContext) -> Result<String, TemplateError> {
+        Ok(self.registry.render("digest_summary", context)?)
+    }
+
+    pub fn source(&self, context: &impl Serialize) -> Result<String, TemplateError> {
+        Ok(self.registry.render("source", context)?)
+    }
+
+    // chat is the body of the chat message of a triggered, or resolved, alert
+    pub fn chat(&self, context: &Context) -> Result<String, TemplateError> {
+        let name = match context.status.as_str() {
+            "resolved" => "chat_resolved",
+            _ => "chat",
+        };
+
+        Ok(self.registry.render(name, context)?)
+    }
+
+    pub fn custom_details(
+        &self,
+        context: &Context,
+    ) -> Result<BTreeMap<String, String>, TemplateError> {
+        self.custom_details
+            .iter()
+            .map(|name| {
+                let value = self.registry.render(&custom_detail(name), context)?;
+                Ok((name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+fn register(
+    registry: &mut Handlebars<'static>,
+    name: &str,
+    template: &str,
+) -> Result<(), TemplateError> {
+    registry
+        .register_template_string(name, template)
+        .map_err(|e| TemplateError::InvalidTemplate(name.to_string(), Box::new(e)))
+}
+
+fn custom_detail(name: &str) -> String {
+    format!("custom_details.{}", name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::thresholds::Severity;
+
+    fn alert() -> Alert {
+        Alert {
+            account_id: "123456789012".to_string(),
+            region: "eu-west-1".to_string(),
+            service_code: "ec2".to_string(),
+            quota_code: "L-1216C47A".to_string(),
+            name: "Running On-Demand Standard instances".to_string(),
+            severity: Some(Severity::Error),
+            team: Some("platform".to_string()),
+            percentage: Some(92),
+            unsent: false,
+            opened_at: 0,
+            triggered_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_default_templates() {
+        let templates = Templates::default();
+        let metadata = QuotaMetadata::default();
+        let context = Context::new(
+            "triggered",
+            "L-1216C47A-eu-west-1-123456789012",
+            &alert(),
+            &metadata,
+        );
+
+        assert_eq!(
+            templates.summary(&context).unwrap(),
+            "Service Quota Utilization 92%: L-1216C47A - Running On-Demand Standard instances in 123456789012 - eu-west-1"
+        );
+        assert_eq!(
+            templates.chat(&context).unwrap(),
+            "[error] Service Quota Utilization 92%: L-1216C47A - Running On-Demand Standard instances in 123456789012 - eu-west-1\nhttps://eu-west-1.console.aws.amazon.com/servicequotas/home/services/ec2/quotas/L-1216C47A"
+        );
+        assert!(templates.custom_details(&context).unwrap().is_empty());
+
+        let metadata = QuotaMetadata {
+            runbook_url: Some("https://wiki.example.com/quotas/ec2".to_string()),
+            account_alias: Some("payments-prod".to_string()),
+            environment: Some("production".to_string()),
+        };
+        let context = Context::new(
+            "triggered",
+            "L-1216C47A-eu-west-1-123456789012",
+            &alert(),
+            &metadata,
+        );
+        assert_eq!(
+            templates.chat(&context).unwrap(),
+            "[error] Service Quota Utilization 92%: L-1216C47A - Running On-Demand Standard instances in 123456789012 (payments-prod) - eu-west-1\nhttps://eu-west-1.console.aws.amazon.com/servicequotas/home/services/ec2/quotas/L-1216C47A\nRunbook: https://wiki.example.com/quotas/ec2"
+        );
+
+        let digest = DigestContext {
+            account_id: "123456789012".to_string(),
+            account_alias: None,
+            environment: None,
+            service: None,
+            team: None,
+            severity: "error".to_string(),
+            count: 2,
+            quotas: vec![],
+        };
+        assert_eq!(
+            templates.digest_summary(&digest).unwrap(),
+            "Service Quota Utilization: 2 quotas breached in 123456789012"
+        );
+    }
+
+    #[test]
+    fn test_custom_templates() {
+        let config = Config::parse(
+            r#"
+            [templates]
+            summary = "{{team}}: {{quota_name}} at {{utilization_percentage}}% & rising{{#if usage}} ({{usage}}){{/if}}"
+
+            [templates.custom_details]
+            runbook = "https://wiki.example.com/quotas/{{service_code}}/{{quota_code}}"
+            stage = "{{#if (eq account_id \"123456789012\")}}production{{else}}staging{{/if}}"
+            "#,
+        )
+        .unwrap();
+        let templates = Templates::new(&config.templates).unwrap();
+        let context = Context::new(
+            "triggered",
+            "L-1216C47A-eu-west-1-123456789012",
+            &alert(),
+            &QuotaMetadata::default(),
+        );
+
+        assert_eq!(
+            templates.summary(&context).unwrap(),
+            "platform: Running On-Demand Standard instances at 92% & rising"
+        );
+        assert_eq!(
+            templates.custom_details(&context).unwrap(),
+            BTreeMap::from([
+                ("stage".to_string(), "production".to_string()),
+                (
+                    "runbook".to_string(),
+                    "https://wiki.example.com/quotas/ec2/L-1216C47A".to_string()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_invalid_template() {
+        let config = TemplatesConfig {
+            chat: Some("{{#if severity}}unclosed".to_string()),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            Templates::new(&config),
+            Err(TemplateError::InvalidTemplate(name, _)) if name == "chat"
+        ));
+
+        let config = TemplatesConfig {
+            custom_details: BTreeMap::from([("team".to_string(), "{{team}}".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Templates::new(&config),
+            Err(TemplateError::ReservedDetail(name)) if name == "team"
+        ));
+    }
+}
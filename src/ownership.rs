@@ -0,0 +1,189 @@
+// Ownership routes the alerts of each quota to the team that owns it, from the ownership rules
+// of the config file, so that breaches reach the owners of the workload directly.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::config::{OwnershipRule, Team};
+
+#[derive(Debug)]
+pub enum OwnershipError {
+    UnknownTeam(String),
+}
+
+impl Error for OwnershipError {}
+impl Display for OwnershipError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::UnknownTeam(team) => write!(f, "UnknownTeam: {}", team),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ownership {
+    teams: Vec<Team>,
+    rules: Vec<OwnershipRule>,
+    // the tags of each account, for the rules that match account tags
+    account_tags: HashMap<String, HashMap<String, String>>,
+    // the accounts whose tags could not be listed
+    unknown_tags: HashSet<String>,
+}
+
+impl Ownership {
+    pub fn new(teams: &[Team], rules: &[OwnershipRule]) -> Result<Self, OwnershipError> {
+        if let Some(rule) = rules
+            .iter()
+            .find(|rule| !teams.iter().any(|team| team.name == rule.team))
+        {
+            return Err(OwnershipError::UnknownTeam(rule.team.clone()));
+        }
+
+        Ok(Self {
+            teams: teams.to_vec(),
+            rules: rules.to_vec(),
+            account_tags: HashMap::new(),
+            unknown_tags: HashSet::new(),
+        })
+    }
+
+    // matches_account_tags is true when a rule matches account tags, which then need listing
+    pub fn matches_account_tags(&self) -> bool {
+        self.rules.iter().any(|rule| rule.account_tag.is_some())
+    }
+
+    pub fn set_account_tags(&mut self, account_id: &str, tags: HashMap<String, String>) {
+        self.account_tags.insert(account_id.to_string(), tags);
+    }
+
+    // set_unknown_tags records an account whose tags could not be listed, so that the owner of
+    // its quotas is not guessed without them
+    pub fn set_unknown_tags(&mut self, account_id: &str) {
+        self.unknown_tags.insert(account_id.to_string());
+    }
+
+    // is_owner_known is false when a rule matching account tags could match the quota, but the
+    // tags of its account could not be listed
+    pub fn is_owner_known(
+        &self,
+        quota_code: &str,
+        service_code: &str,
+        account_id: &str,
+        region: &str,
+    ) -> bool {
+        !self.unknown_tags.contains(account_id)
+            || !self.rules.iter().any(|rule| {
+                rule.account_tag.is_some()
+                    && rule
+                        .matcher
+                        .matches_fields(quota_code, service_code, account_id, region)
+            })
+    }
+
+    pub fn teams(&self) -> &[Team] {
+        &self.teams
+    }
+
+    pub fn team(&self, name: &str) -> Option<&Team> {
+        self.teams.iter().find(|team| team.name == name)
+    }
+
+    // owner is the team of the last rule matching the quota, so that later rules take precedence
+    pub fn owner(
+        &self,
+        quota_code: &str,
+        service_code: &str,
+        account_id: &str,
+        region: &str,
+    ) -> Option<&Team> {
+        let rule = self.rules.iter().rev().find(|rule| {
+            rule.matcher
+                .matches_fields(quota_code, service_code, account_id, region)
+                && match &rule.account_tag {
+                    Some(tag) => {
+                        self.account_tags
+                            .get(account_id)
+                            .and_then(|tags| tags.get(&tag.key))
+                            == Some(&tag.value)
+                    }
+                    None => true,
+                }
+        })?;
+
+        self.team(&rule.team)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{AccountTag, Matcher};
+
+    fn team(name: &str) -> Team {
+        Team {
+            name: name.to_string(),
+            routing_key: Some(format!("{}-key", name)),
+            routing_key_env: None,
+            slack_channel: None,
+        }
+    }
+
+    fn rule(
+        team: &str,
+        account_id: Option<&str>,
+        account_tag: Option<(&str, &str)>,
+    ) -> OwnershipRule {
+        OwnershipRule {
+            matcher: Matcher {
+                account_id: account_id.map(|a| a.to_string()),
+                ..Default::default()
+            },
+            account_tag: account_tag.map(|(key, value)| AccountTag {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            team: team.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_owner() {
+        let mut ownership = Ownership::new(
+            &[team("platform"), team("payments"), team("search")],
+            &[
+                rule("platform", None, None),
+                rule("payments", Some("111111111111"), None),
+                rule("search", None, Some(("team", "search"))),
+            ],
+        )
+        .unwrap();
+        ownership.set_account_tags(
+            "222222222222",
+            HashMap::from([("team".to_string(), "search".to_string())]),
+        );
+
+        let owner = |account_id| {
+            ownership
+                .owner("L-1216C47A", "ec2", account_id, "us-east-1")
+                .map(|team| team.name.as_str())
+        };
+
+        assert!(ownership.matches_account_tags());
+        assert_eq!(owner("111111111111"), Some("payments"));
+        assert_eq!(owner("222222222222"), Some("search"));
+        assert_eq!(owner("333333333333"), Some("platform"));
+
+        ownership.set_unknown_tags("333333333333");
+        assert!(ownership.is_owner_known("L-1216C47A", "ec2", "222222222222", "us-east-1"));
+        assert!(!ownership.is_owner_known("L-1216C47A", "ec2", "333333333333", "us-east-1"));
+    }
+
+    #[test]
+    fn test_unknown_team() {
+        assert!(matches!(
+            Ownership::new(&[team("platform")], &[rule("payments", None, None)]),
+            Err(OwnershipError::UnknownTeam(_))
+        ));
+    }
+}
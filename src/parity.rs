@@ -0,0 +1,211 @@
+// Parity compares the same quota across the regions scanned, to find quotas whose applied value
+// differs between regions, e.g. a DR region with a lower vCPU quota than the primary region.
+
+use std::collections::BTreeMap;
+
+use crate::quotas::{Quota, GLOBAL_REGION};
+
+// Observation is the applied value and utilization of a quota in a region, where known
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub account_id: String,
+    pub service_code: String,
+    pub quota_code: String,
+    pub name: String,
+    pub region: String,
+    pub limit: Option<f64>,
+    pub percentage: Option<u8>,
+}
+
+impl Observation {
+    // the limit is the applied value listed by Service Quotas, so that idle regions without
+    // usage data are still compared, or else the limit of the utilization
+    pub async fn new(quota: &dyn Quota) -> Self {
+        let utilization = quota.utilization().await.ok();
+        let limit = match quota.applied_value().await {
+            Some(applied_value) => Some(applied_value),
+            None => utilization.and_then(|u| u.limit),
+        };
+
+        Self {
+            account_id: quota.account_id().await.to_string(),
+            service_code: quota.service_code().await.to_string(),
+            quota_code: quota.quota_code().await.to_string(),
+            name: quota.name().await.to_string(),
+            region: quota.region().await.to_string(),
+            limit,
+            percentage: utilization.map(|u| u.percentage),
+        }
+    }
+}
+
+// Parity is a quota of an account across every region it was scanned in, sorted by region
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parity {
+    pub account_id: String,
+    pub service_code: String,
+    pub quota_code: String,
+    pub name: String,
+    pub regions: Vec<Observation>,
+}
+
+impl Parity {
+    // differs is true when the applied value of the quota is not the same in every region it is
+    // known in
+    pub fn differs(&self) -> bool {
+        match (self.lowest(), self.highest()) {
+            (Some(lowest), Some(highest)) => lowest.limit != highest.limit,
+            _ => false,
+        }
+    }
+
+    pub fn lowest(&self) -> Option<&Observation> {
+        self.known().min_by(|a, b| by_limit(a, b))
+    }
+
+    pub fn highest(&self) -> Option<&Observation> {
+        self.known().max_by(|a, b| by_limit(a, b))
+    }
+
+    fn known(&self) -> impl Iterator<Item = &Observation> {
+        self.regions.iter().filter(|o| o.limit.is_some())
+    }
+}
+
+// by_limit orders observations by their limit, with a total order so that a NaN limit does not
+// panic
+fn by_limit(a: &Observation, b: &Observation) -> std::cmp::Ordering {
+    let limit = |o: &Observation| o.limit.unwrap_or_default();
+    limit(a).total_cmp(&limit(b))
+}
+
+// group groups the observations by account and quota code. Global quotas, and quotas scanned in
+// a single region, have nothing to compare and are left out.
+pub fn group(observations: Vec<Observation>) -> Vec<Parity> {
+    let mut groups: BTreeMap<(String, String), Vec<Observation>> = BTreeMap::new();
+
+    for observation in observations {
+        if observation.region == GLOBAL_REGION {
+            continue;
+        }
+
+        groups
+            .entry((
+                observation.account_id.clone(),
+                observation.quota_code.clone(),
+            ))
+            .or_default()
+            .push(observation);
+    }
+
+    groups
+        .into_values()
+        .filter(|regions| regions.len() > 1)
+        .map(|mut regions| {
+            regions.sort_by(|a, b| a.region.cmp(&b.region));
+            let first = &regions[0];
+
+            Parity {
+                account_id: first.account_id.clone(),
+                service_code: first.service_code.clone(),
+                quota_code: first.quota_code.clone(),
+                name: first.name.clone(),
+                regions,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quotas::{Source, TestQuota, Utilization, UtilizationError};
+
+    fn observation(quota_code: &str, region: &str, limit: Option<f64>) -> Observation {
+        Observation {
+            account_id: "123456789012".to_string(),
+            service_code: "ec2".to_string(),
+            quota_code: quota_code.to_string(),
+            name: quota_code.to_string(),
+            region: region.to_string(),
+            limit,
+            percentage: limit.map(|_| 50),
+        }
+    }
+
+    #[test]
+    fn test_group() {
+        let parities = group(vec![
+            observation("L-1216C47A", "us-west-2", Some(128.0)),
+            observation("L-1216C47A", "us-east-1", Some(512.0)),
+            observation("L-0263D0A3", "us-east-1", Some(5.0)),
+            observation("L-0263D0A3", "us-west-2", Some(5.0)),
+            observation("L-0263D0A3", "eu-west-1", None),
+            observation("L-F678F1CE", "us-east-1", Some(5.0)),
+            observation("L-2ACBD22F", "global", Some(75.0)),
+        ]);
+
+        assert_eq!(parities.len(), 2);
+
+        assert_eq!(parities[0].quota_code, "L-0263D0A3");
+        assert!(!parities[0].differs());
+        assert_eq!(
+            parities[0]
+                .regions
+                .iter()
+                .map(|o| o.region.as_str())
+                .collect::<Vec<_>>(),
+            vec!["eu-west-1", "us-east-1", "us-west-2"]
+        );
+
+        assert_eq!(parities[1].quota_code, "L-1216C47A");
+        assert!(parities[1].differs());
+        assert_eq!(parities[1].lowest().unwrap().region, "us-west-2");
+        assert_eq!(parities[1].highest().unwrap().region, "us-east-1");
+    }
+
+    #[test]
+    fn test_nan_limit() {
+        let parities = group(vec![
+            observation("L-1216C47A", "us-east-1", Some(512.0)),
+            observation("L-1216C47A", "us-west-2", Some(f64::NAN)),
+            observation("L-1216C47A", "eu-west-1", Some(128.0)),
+        ]);
+
+        assert_eq!(parities[0].lowest().unwrap().region, "eu-west-1");
+        assert_eq!(parities[0].highest().unwrap().region, "us-west-2");
+    }
+
+    #[tokio::test]
+    async fn test_observation_applied_value() {
+        // the DR region is idle, so it has an applied value but no utilization
+        let primary = TestQuota::new(
+            "L-1216C47A",
+            "us-east-1",
+            Ok(Utilization {
+                percentage: 50,
+                source: Source::CloudWatch,
+                usage: Some(256.0),
+                limit: Some(512.0),
+            }),
+        );
+        let dr = TestQuota {
+            applied_value: Some(128.0),
+            ..TestQuota::new(
+                "L-1216C47A",
+                "us-west-2",
+                Err(UtilizationError::MissingMetricData),
+            )
+        };
+
+        let parities = group(vec![
+            Observation::new(&primary).await,
+            Observation::new(&dr).await,
+        ]);
+
+        assert_eq!(parities[0].regions[1].limit, Some(128.0));
+        assert_eq!(parities[0].regions[1].percentage, None);
+        assert!(parities[0].differs());
+        assert_eq!(parities[0].lowest().unwrap().region, "us-west-2");
+    }
+}
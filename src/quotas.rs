@@ -1,18 +1,22 @@
+pub mod ec2;
 pub mod lambda;
 
 use async_trait::async_trait;
+use aws_sdk_cloudwatch::types::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
 use std::error::Error;
-use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::services::cloudwatch::{Client, ServiceQuotaUtilizationQueryInput};
+use crate::services::cloudwatch::{Client, CloudWatchError, ServiceQuotaUtilizationQueryInput};
+use crate::util;
 
 #[derive(Debug, Clone)]
 pub struct QuotaCloudWatch {
     quota_details: QuotaDetails,
     cloudwatch: Option<CloudWatchQuotaDetails>,
-    utilization: Arc<RwLock<Option<u8>>>,
+    utilization: Arc<RwLock<Option<Result<Utilization, UtilizationError>>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -55,6 +59,47 @@ impl QuotaCloudWatch {
     }
 }
 
+// Source records where the usage of a quota was obtained from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    // the AWS/Usage metrics in CloudWatch, lagging by up to an hour
+    CloudWatch,
+    // the raw AWS/Usage metrics divided by the applied quota value, used when the
+    // SERVICE_QUOTA() metric math yields no data
+    CloudWatchFallback,
+    // a live count from the service's own resource API
+    Api,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::CloudWatch => write!(f, "cloudwatch"),
+            Self::CloudWatchFallback => write!(f, "cloudwatch-fallback"),
+            Self::Api => write!(f, "api"),
+        }
+    }
+}
+
+// Utilization is the percentage of a quota in use, along with the source of the value. The
+// usage and limit are the absolute values, where the source provides them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Utilization {
+    pub percentage: u8,
+    pub source: Source,
+    pub usage: Option<f64>,
+    pub limit: Option<f64>,
+}
+
+impl Utilization {
+    // headroom is the number of units of the quota remaining
+    pub fn headroom(&self) -> Option<f64> {
+        self.usage
+            .zip(self.limit)
+            .map(|(usage, limit)| limit - usage)
+    }
+}
+
 // Quota provides a common interface for all quotas.
 #[async_trait]
 pub trait Quota: Send + Sync {
@@ -64,7 +109,10 @@ pub trait Quota: Send + Sync {
     async fn quota_code(&self) -> &str;
     async fn service_code(&self) -> &str;
     async fn region(&self) -> &str;
-    async fn utilization(&self) -> Option<u8>;
+    // applied_value is the value of the quota applied to the account, as listed by Service
+    // Quotas, where known without evaluating the utilization
+    async fn applied_value(&self) -> Option<f64>;
+    async fn utilization(&self) -> Result<Utilization, UtilizationError>;
 }
 
 #[async_trait]
@@ -93,25 +141,156 @@ impl Quota for QuotaCloudWatch {
         &self.quota_details.region
     }
 
-    async fn utilization(&self) -> Option<u8> {
-        if let Some(utilization) = *self.utilization.read().await {
-            return Some(utilization);
+    async fn applied_value(&self) -> Option<f64> {
+        self.cloudwatch
+            .as_ref()
+            .and_then(|cloudwatch| cloudwatch.query.applied_value)
+    }
+
+    async fn utilization(&self) -> Result<Utilization, UtilizationError> {
+        if let Some(utilization) = self.utilization.read().await.clone() {
+            return utilization;
         }
 
-        if let Some(cloudwatch) = self.cloudwatch.clone() {
-            let utilization = cloudwatch
+        let utilization = match self.cloudwatch.clone() {
+            Some(cloudwatch) => cloudwatch
                 .client
                 .service_quota_utilization(&cloudwatch.query)
                 .await
-                .ok();
+                .map(|utilization| Utilization {
+                    percentage: utilization.percentage,
+                    source: match utilization.fallback {
+                        true => Source::CloudWatchFallback,
+                        false => Source::CloudWatch,
+                    },
+                    usage: utilization.usage,
+                    limit: utilization.limit,
+                })
+                .map_err(UtilizationError::from),
+            None => Err(UtilizationError::Unsupported),
+        };
+
+        *self.utilization.write().await = Some(utilization.clone());
+        utilization
+    }
+}
+
+// the region reported for global quotas, which apply to the whole account
+pub const GLOBAL_REGION: &str = "global";
+
+// GlobalQuota reports a quota that applies to the whole account with the region "global". It is
+// evaluated in the region it was discovered in.
+pub struct GlobalQuota {
+    quota: Box<dyn Quota>,
+}
+
+impl GlobalQuota {
+    pub fn new(quota: Box<dyn Quota>) -> Self {
+        Self { quota }
+    }
+}
+
+#[async_trait]
+impl Quota for GlobalQuota {
+    async fn name(&self) -> &str {
+        self.quota.name().await
+    }
+
+    async fn arn(&self) -> &str {
+        self.quota.arn().await
+    }
+
+    async fn account_id(&self) -> &str {
+        self.quota.account_id().await
+    }
+
+    async fn quota_code(&self) -> &str {
+        self.quota.quota_code().await
+    }
+
+    async fn service_code(&self) -> &str {
+        self.quota.service_code().await
+    }
+
+    async fn region(&self) -> &str {
+        GLOBAL_REGION
+    }
+
+    async fn applied_value(&self) -> Option<f64> {
+        self.quota.applied_value().await
+    }
+
+    async fn utilization(&self) -> Result<Utilization, UtilizationError> {
+        self.quota.utilization().await
+    }
+}
+
+// UtilizationError describes why the utilization of a quota could not be determined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UtilizationError {
+    // the credentials are not permitted to call the API, usually an incomplete IAM policy
+    AccessDenied(String),
+    // the API rate limited the request, even after retries
+    Throttled(String),
+    // the usage metrics returned no datapoints in the query window
+    MissingMetricData,
+    // there is no usage metric or collector for the quota
+    Unsupported,
+    // any other error returned by an AWS API
+    Api(String),
+}
+
+impl UtilizationError {
+    // from_sdk_error classifies an AWS SDK error by the error code returned from the service
+    pub fn from_sdk_error<E: ProvideErrorKind + Error, R: Debug>(err: &SdkError<E, R>) -> Self {
+        let message = err.to_string();
+
+        let code = util::sdk_error_code(err);
+
+        match code {
+            Some("AccessDenied" | "AccessDeniedException" | "UnauthorizedOperation") => {
+                Self::AccessDenied(message)
+            }
+            _ if util::is_throttling_error_code(code) => Self::Throttled(message),
+            _ => Self::Api(message),
+        }
+    }
+
+    // is_failure is true when the error is a failure to scan the quota, rather than the quota
+    // having no usage data to report
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Self::MissingMetricData | Self::Unsupported)
+    }
+
+    // cause is the name of the error, without the detail, for grouping errors in reports
+    pub fn cause(&self) -> &'static str {
+        match self {
+            Self::AccessDenied(_) => "AccessDenied",
+            Self::Throttled(_) => "Throttled",
+            Self::MissingMetricData => "MissingMetricData",
+            Self::Unsupported => "Unsupported",
+            Self::Api(_) => "ApiError",
+        }
+    }
+}
 
-            if let Some(utilization) = utilization {
-                *self.utilization.write().await = Some(utilization);
+impl Error for UtilizationError {}
+impl Display for UtilizationError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::AccessDenied(e) | Self::Throttled(e) | Self::Api(e) => {
+                write!(f, "{}: {}", self.cause(), e)
             }
+            Self::MissingMetricData | Self::Unsupported => write!(f, "{}", self.cause()),
+        }
+    }
+}
 
-            utilization
-        } else {
-            None
+impl From<CloudWatchError> for UtilizationError {
+    fn from(err: CloudWatchError) -> Self {
+        match err {
+            CloudWatchError::MissingMetricData => Self::MissingMetricData,
+            CloudWatchError::AwsCloudWatchSdkError(e) => Self::from_sdk_error(&e),
         }
     }
 }
@@ -132,17 +311,17 @@ impl Display for QuotaError {
 
 // a ParsedArn defines the individual components of an AWS Service Quota Arn
 #[derive(Debug, Clone)]
-struct ParsedArn {
-    region: String,
-    account_id: String,
-    service_code: String,
-    quota_code: String,
+pub struct ParsedArn {
+    pub region: String,
+    pub account_id: String,
+    pub service_code: String,
+    pub quota_code: String,
 }
 
 // split_arn splits a service quota ARN into the fields of interest, returning:
 // region, account, service code, quota code
 // An Arn is of the form: arn:${Partition}:servicequotas:${Region}:${Account}:${ServiceCode}/${QuotaCode}
-fn parse_arn(arn: &str) -> Result<ParsedArn, QuotaError> {
+pub fn parse_arn(arn: &str) -> Result<ParsedArn, QuotaError> {
     // splice the quota code from the end of the Arn
     let (arn_components, quota_code) = arn.split_once('/').ok_or_else(|| {
         QuotaError::ArnFormatError(format!("failed to parse quota code from arn: {}", arn))
@@ -167,9 +346,69 @@ fn parse_arn(arn: &str) -> Result<ParsedArn, QuotaError> {
     })
 }
 
+// TestQuota is a quota with fixed values, for the tests of the modules that evaluate quotas
+#[cfg(test)]
+pub struct TestQuota {
+    pub arn: String,
+    pub quota_code: String,
+    pub region: String,
+    pub applied_value: Option<f64>,
+    pub utilization: Result<Utilization, UtilizationError>,
+}
+
+#[cfg(test)]
+impl TestQuota {
+    pub fn new(
+        quota_code: &str,
+        region: &str,
+        utilization: Result<Utilization, UtilizationError>,
+    ) -> Self {
+        Self {
+            arn: format!(
+                "arn:aws:servicequotas:{}:123456789012:ec2/{}",
+                region, quota_code
+            ),
+            quota_code: quota_code.to_string(),
+            region: region.to_string(),
+            applied_value: None,
+            utilization,
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Quota for TestQuota {
+    async fn name(&self) -> &str {
+        &self.quota_code
+    }
+    async fn arn(&self) -> &str {
+        &self.arn
+    }
+    async fn account_id(&self) -> &str {
+        "123456789012"
+    }
+    async fn quota_code(&self) -> &str {
+        &self.quota_code
+    }
+    async fn service_code(&self) -> &str {
+        "ec2"
+    }
+    async fn region(&self) -> &str {
+        &self.region
+    }
+    async fn applied_value(&self) -> Option<f64> {
+        self.applied_value
+    }
+    async fn utilization(&self) -> Result<Utilization, UtilizationError> {
+        self.utilization.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aws_sdk_cloudwatch::error::GetMetricDataError;
 
     #[test]
     fn test_parse_arn() {
@@ -193,6 +432,53 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_utilization_headroom() {
+        let utilization = Utilization {
+            percentage: 80,
+            source: Source::Api,
+            usage: Some(4.0),
+            limit: Some(5.0),
+        };
+        assert_eq!(utilization.headroom(), Some(1.0));
+
+        let utilization = Utilization {
+            usage: None,
+            ..utilization
+        };
+        assert_eq!(utilization.headroom(), None);
+    }
+
+    #[test]
+    fn test_utilization_error_from_sdk_error() {
+        let codes = vec![
+            ("AccessDenied", "AccessDenied"),
+            ("AccessDeniedException", "AccessDenied"),
+            ("Throttling", "Throttled"),
+            ("TooManyRequestsException", "Throttled"),
+            ("InvalidParameterValue", "ApiError"),
+        ];
+
+        for (code, cause) in codes {
+            let err: SdkError<GetMetricDataError, ()> = SdkError::ServiceError {
+                err: GetMetricDataError::generic(
+                    aws_smithy_types::Error::builder().code(code).build(),
+                ),
+                raw: (),
+            };
+
+            assert_eq!(UtilizationError::from_sdk_error(&err).cause(), cause);
+        }
+    }
+
+    #[test]
+    fn test_utilization_error_from_cloudwatch_error() {
+        assert_eq!(
+            UtilizationError::from(CloudWatchError::MissingMetricData),
+            UtilizationError::MissingMetricData
+        );
+    }
+
     #[test]
     fn test_parse_arn_errors() {
         let arns = vec![(
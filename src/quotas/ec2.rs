@@ -0,0 +1,212 @@
+use crate::{
+    quotas,
+    quotas::{Quota, QuotaError, Source, Utilization, UtilizationError},
+    scheduler::{Api, Scheduler},
+    util,
+};
+use async_trait::async_trait;
+use aws_sdk_ec2::{
+    self, error::DescribeAddressesError, model::Filter, output::DescribeAddressesOutput,
+    types::SdkError,
+};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    sync::Arc,
+};
+use tokio::sync::RwLock;
+
+struct Client {
+    client: aws_sdk_ec2::Client,
+    region: String,
+    scheduler: Arc<Scheduler>,
+}
+
+#[derive(Debug)]
+pub enum Ec2Error {
+    // issues with parsing ARNs
+    ArnFormatError(String),
+    AwsEc2SdkError(Box<SdkError<DescribeAddressesError>>),
+}
+
+impl Error for Ec2Error {}
+impl Display for Ec2Error {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::ArnFormatError(e) => write!(f, "ArnFormatError: {}", e),
+            Self::AwsEc2SdkError(e) => write!(f, "AwsEc2SdkError: {}", e),
+        }
+    }
+}
+
+impl From<SdkError<DescribeAddressesError>> for Ec2Error {
+    fn from(err: SdkError<DescribeAddressesError>) -> Self {
+        Self::AwsEc2SdkError(Box::new(err))
+    }
+}
+
+impl From<QuotaError> for Ec2Error {
+    fn from(err: QuotaError) -> Self {
+        Self::ArnFormatError(err.to_string())
+    }
+}
+
+impl From<Ec2Error> for UtilizationError {
+    fn from(err: Ec2Error) -> Self {
+        match err {
+            Ec2Error::ArnFormatError(e) => Self::Api(e),
+            Ec2Error::AwsEc2SdkError(e) => Self::from_sdk_error(&e),
+        }
+    }
+}
+
+impl Client {
+    pub async fn new(region: &str, scheduler: Arc<Scheduler>) -> Self {
+        let (config, retries) = util::aws_config_with_region(region).await;
+
+        let client_config = aws_sdk_ec2::config::Builder::from(&config)
+            .retry_config(retries)
+            .build();
+
+        let client = aws_sdk_ec2::Client::from_conf(client_config);
+
+        Self {
+            client,
+            region: region.to_string(),
+            scheduler,
+        }
+    }
+
+    pub async fn describe_vpc_addresses(&self) -> Result<DescribeAddressesOutput, Ec2Error> {
+        self.scheduler
+            .call(Api::Ec2, &self.region, || {
+                self.client
+                    .describe_addresses()
+                    .filters(Filter::builder().name("domain").values("vpc").build())
+                    .send()
+            })
+            .await
+            .map_err(|e| e.into())
+    }
+}
+
+// The maximum number of Elastic IP addresses that you can allocate for EC2-VPC in this Region.
+pub struct QuotaL0263D0A3 {
+    client: Client,
+    utilization: Arc<RwLock<Option<Result<Utilization, UtilizationError>>>>,
+    limit: f64,
+    arn: String,
+    account_id: String,
+    name: String,
+    quota_code: String,
+    service_code: String,
+    region: String,
+}
+
+#[allow(clippy::redundant_field_names)]
+impl QuotaL0263D0A3 {
+    pub async fn new(
+        arn: &str,
+        name: &str,
+        limit: f64,
+        scheduler: Arc<Scheduler>,
+    ) -> Result<Self, Ec2Error> {
+        let parsed_arn = quotas::parse_arn(arn)?;
+        let client = Client::new(&parsed_arn.region, scheduler).await;
+
+        Ok(Self {
+            client: client,
+            utilization: Arc::new(RwLock::new(None)),
+            limit: limit,
+            arn: arn.to_string(),
+            name: name.to_string(),
+            account_id: parsed_arn.account_id,
+            quota_code: parsed_arn.quota_code,
+            service_code: parsed_arn.service_code,
+            region: parsed_arn.region,
+        })
+    }
+
+    async fn fetch_utilization(&self) -> Result<Utilization, UtilizationError> {
+        let response = self.client.describe_vpc_addresses().await?;
+        let used = response.addresses().map(|a| a.len()).unwrap_or_default();
+
+        Ok(Utilization {
+            percentage: self.calculate_utilization(used),
+            source: Source::Api,
+            usage: Some(used as f64),
+            limit: Some(self.limit),
+        })
+    }
+
+    fn calculate_utilization(&self, used: usize) -> u8 {
+        if self.limit <= 0.0 {
+            return 0;
+        }
+
+        (used as f64 / self.limit * 100.0) as u8
+    }
+}
+
+#[async_trait]
+impl Quota for QuotaL0263D0A3 {
+    async fn arn(&self) -> &str {
+        &self.arn
+    }
+
+    async fn account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn quota_code(&self) -> &str {
+        &self.quota_code
+    }
+
+    async fn service_code(&self) -> &str {
+        &self.service_code
+    }
+
+    async fn region(&self) -> &str {
+        &self.region
+    }
+
+    async fn applied_value(&self) -> Option<f64> {
+        Some(self.limit)
+    }
+
+    async fn utilization(&self) -> Result<Utilization, UtilizationError> {
+        if let Some(utilization) = self.utilization.read().await.clone() {
+            return utilization;
+        }
+
+        let utilization = self.fetch_utilization().await;
+
+        *self.utilization.write().await = Some(utilization.clone());
+        utilization
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_calculate_utilization() {
+        let quota = QuotaL0263D0A3::new(
+            "arn:aws:servicequotas:us-east-1:123456789012:ec2/L-0263D0A3",
+            "EC2-VPC Elastic IPs",
+            5.0,
+            Arc::new(Scheduler::default()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(quota.calculate_utilization(0), 0);
+        assert_eq!(quota.calculate_utilization(4), 80);
+        assert_eq!(quota.calculate_utilization(5), 100);
+    }
+}
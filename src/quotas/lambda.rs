@@ -1,6 +1,7 @@
 use crate::{
     quotas,
-    quotas::{Quota, QuotaError},
+    quotas::{Quota, QuotaError, Source, Utilization, UtilizationError},
+    scheduler::{Api, Scheduler},
     util,
 };
 use async_trait::async_trait;
@@ -16,13 +17,15 @@ use tokio::sync::RwLock;
 
 struct Client {
     client: aws_sdk_lambda::Client,
+    region: String,
+    scheduler: Arc<Scheduler>,
 }
 
 #[derive(Debug)]
 pub enum LambdaError {
     // issues with parsing ARNs
     ArnFormatError(String),
-    AwsLambdaSdkError(SdkError<GetAccountSettingsError>),
+    AwsLambdaSdkError(Box<SdkError<GetAccountSettingsError>>),
 }
 
 impl Error for LambdaError {}
@@ -37,7 +40,7 @@ impl Display for LambdaError {
 
 impl From<SdkError<GetAccountSettingsError>> for LambdaError {
     fn from(err: SdkError<GetAccountSettingsError>) -> Self {
-        Self::AwsLambdaSdkError(err)
+        Self::AwsLambdaSdkError(Box::new(err))
     }
 }
 
@@ -47,8 +50,17 @@ impl From<QuotaError> for LambdaError {
     }
 }
 
+impl From<LambdaError> for UtilizationError {
+    fn from(err: LambdaError) -> Self {
+        match err {
+            LambdaError::ArnFormatError(e) => Self::Api(e),
+            LambdaError::AwsLambdaSdkError(e) => Self::from_sdk_error(&e),
+        }
+    }
+}
+
 impl Client {
-    pub async fn new(region: &str) -> Self {
+    pub async fn new(region: &str, scheduler: Arc<Scheduler>) -> Self {
         let (config, retries) = util::aws_config_with_region(region).await;
 
         let client_config = aws_sdk_lambda::config::Builder::from(&config)
@@ -57,13 +69,18 @@ impl Client {
 
         let client = aws_sdk_lambda::Client::from_conf(client_config);
 
-        Self { client }
+        Self {
+            client,
+            region: region.to_string(),
+            scheduler,
+        }
     }
 
     pub async fn get_account_settings(&self) -> Result<GetAccountSettingsOutput, LambdaError> {
-        self.client
-            .get_account_settings()
-            .send()
+        self.scheduler
+            .call(Api::Lambda, &self.region, || {
+                self.client.get_account_settings().send()
+            })
             .await
             .map_err(|e| e.into())
     }
@@ -72,7 +89,7 @@ impl Client {
 // The amount of storage that's available for deployment packages and layer archives in the current Region.
 pub struct QuotaL2ACBD22F {
     client: Client,
-    utilization: Arc<RwLock<Option<u8>>>,
+    utilization: Arc<RwLock<Option<Result<Utilization, UtilizationError>>>>,
     arn: String,
     account_id: String,
     name: String,
@@ -83,9 +100,13 @@ pub struct QuotaL2ACBD22F {
 
 #[allow(clippy::redundant_field_names)]
 impl QuotaL2ACBD22F {
-    pub async fn new(arn: &str, name: &str) -> Result<Self, LambdaError> {
+    pub async fn new(
+        arn: &str,
+        name: &str,
+        scheduler: Arc<Scheduler>,
+    ) -> Result<Self, LambdaError> {
         let parsed_arn = quotas::parse_arn(arn)?;
-        let client = Client::new(&parsed_arn.region).await;
+        let client = Client::new(&parsed_arn.region, scheduler).await;
 
         Ok(Self {
             client: client,
@@ -99,6 +120,25 @@ impl QuotaL2ACBD22F {
         })
     }
 
+    async fn fetch_utilization(&self) -> Result<Utilization, UtilizationError> {
+        let response = self.client.get_account_settings().await?;
+
+        match (response.account_usage(), response.account_limit()) {
+            (Some(account_usage), Some(account_limit)) => Ok(Utilization {
+                percentage: self.calculate_utilization(
+                    account_usage.total_code_size(),
+                    account_limit.total_code_size(),
+                ),
+                source: Source::Api,
+                usage: Some(account_usage.total_code_size() as f64),
+                limit: Some(account_limit.total_code_size() as f64),
+            }),
+            _ => Err(UtilizationError::Api(
+                "GetAccountSettings returned no account usage or limit".to_string(),
+            )),
+        }
+    }
+
     fn calculate_utilization(&self, used: i64, limit: i64) -> u8 {
         (used as f64 / limit as f64 * 100.0) as u8
     }
@@ -130,26 +170,20 @@ impl Quota for QuotaL2ACBD22F {
         &self.region
     }
 
-    async fn utilization(&self) -> Option<u8> {
-        if let Some(utilization) = *self.utilization.read().await {
-            return Some(utilization);
-        }
-
-        let response = self.client.get_account_settings().await.ok()?;
-
-        if let (Some(account_usage), Some(account_limit)) =
-            (response.account_usage(), response.account_limit())
-        {
-            let utilization = self.calculate_utilization(
-                account_usage.total_code_size(),
-                account_limit.total_code_size(),
-            );
+    // the limit is read from GetAccountSettings with the usage
+    async fn applied_value(&self) -> Option<f64> {
+        None
+    }
 
-            *self.utilization.write().await = Some(utilization as u8);
-            return Some(utilization as u8);
+    async fn utilization(&self) -> Result<Utilization, UtilizationError> {
+        if let Some(utilization) = self.utilization.read().await.clone() {
+            return utilization;
         }
 
-        None
+        let utilization = self.fetch_utilization().await;
+
+        *self.utilization.write().await = Some(utilization.clone());
+        utilization
     }
 }
 
@@ -162,6 +196,7 @@ mod test {
         let quota = QuotaL2ACBD22F::new(
             "arn:aws:servicequotas:us-east-1:123456789012:service/lambda/test_quota",
             "test_quota",
+            Arc::new(Scheduler::default()),
         )
         .await
         .unwrap();
@@ -0,0 +1,175 @@
+// Regions selects the regions to scan from the command line: a list of regions, every region
+// enabled for the account (all), or every enabled region except some (all-except=a,b).
+
+use crate::services::ec2::RegionStatus;
+
+const ALL: &str = "all";
+const ALL_EXCEPT: &str = "all-except=";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionSelector {
+    All { except: Vec<String> },
+    Listed(Vec<String>),
+}
+
+// Selection is the regions to scan, and the regions that were skipped with the reason
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub regions: Vec<String>,
+    pub skipped: Vec<(String, String)>,
+}
+
+impl RegionSelector {
+    pub fn parse(values: &[String]) -> Result<Self, String> {
+        let selector = match values {
+            [value] if value == ALL => Self::All { except: Vec::new() },
+            [value] if value.starts_with(ALL_EXCEPT) => Self::All {
+                except: value[ALL_EXCEPT.len()..]
+                    .split(',')
+                    .map(|r| r.trim().to_string())
+                    .filter(|r| !r.is_empty())
+                    .collect(),
+            },
+            _ => {
+                if values.iter().any(|v| v == ALL || v.starts_with(ALL_EXCEPT)) {
+                    return Err(format!(
+                        "{} and {}... cannot be combined with other regions",
+                        ALL, ALL_EXCEPT
+                    ));
+                }
+
+                let mut regions = Vec::new();
+                for value in values {
+                    if !regions.contains(value) {
+                        regions.push(value.clone());
+                    }
+                }
+                Self::Listed(regions)
+            }
+        };
+
+        Ok(selector)
+    }
+
+    // select resolves the regions to scan from the regions of the partition. Regions that are
+    // not enabled for the account, or do not exist, are skipped.
+    pub fn select(&self, available: &[RegionStatus]) -> Selection {
+        let mut selection = Selection::default();
+
+        match self {
+            Self::All { except } => {
+                let mut available = available.to_vec();
+                available.sort_by(|a, b| a.name.cmp(&b.name));
+
+                for region in available {
+                    if except.contains(&region.name) {
+                        continue;
+                    }
+
+                    if region.enabled {
+                        selection.regions.push(region.name);
+                    } else {
+                        selection
+                            .skipped
+                            .push((region.name, "not enabled for the account".to_string()));
+                    }
+                }
+            }
+            Self::Listed(regions) => {
+                for region in regions {
+                    match available.iter().find(|r| &r.name == region) {
+                        Some(status) if status.enabled => selection.regions.push(region.clone()),
+                        Some(_) => selection
+                            .skipped
+                            .push((region.clone(), "not enabled for the account".to_string())),
+                        None => selection
+                            .skipped
+                            .push((region.clone(), "not a known region".to_string())),
+                    }
+                }
+            }
+        }
+
+        selection
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn values(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn available() -> Vec<RegionStatus> {
+        vec![
+            RegionStatus {
+                name: "us-east-1".to_string(),
+                enabled: true,
+            },
+            RegionStatus {
+                name: "af-south-1".to_string(),
+                enabled: false,
+            },
+            RegionStatus {
+                name: "eu-west-1".to_string(),
+                enabled: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            RegionSelector::parse(&values(&["all"])),
+            Ok(RegionSelector::All { except: vec![] })
+        );
+        assert_eq!(
+            RegionSelector::parse(&values(&["all-except=us-west-1, eu-west-1"])),
+            Ok(RegionSelector::All {
+                except: values(&["us-west-1", "eu-west-1"])
+            })
+        );
+        assert_eq!(
+            RegionSelector::parse(&values(&["us-east-1", "eu-west-1", "us-east-1"])),
+            Ok(RegionSelector::Listed(values(&["us-east-1", "eu-west-1"])))
+        );
+        assert!(RegionSelector::parse(&values(&["all", "us-east-1"])).is_err());
+    }
+
+    #[test]
+    fn test_select_all() {
+        let selection = RegionSelector::All {
+            except: values(&["us-east-1"]),
+        }
+        .select(&available());
+
+        assert_eq!(selection.regions, values(&["eu-west-1"]));
+        assert_eq!(
+            selection.skipped,
+            vec![(
+                "af-south-1".to_string(),
+                "not enabled for the account".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_select_listed() {
+        let selection = RegionSelector::Listed(values(&["eu-west-1", "af-south-1", "xx-east-1"]))
+            .select(&available());
+
+        assert_eq!(selection.regions, values(&["eu-west-1"]));
+        assert_eq!(
+            selection.skipped,
+            vec![
+                (
+                    "af-south-1".to_string(),
+                    "not enabled for the account".to_string()
+                ),
+                ("xx-east-1".to_string(), "not a known region".to_string()),
+            ]
+        );
+    }
+}
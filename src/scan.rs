@@ -0,0 +1,524 @@
+// Scan discovers the quotas of each region and evaluates their utilization as two pipelined
+// stages. Quotas are streamed from discovery into evaluation as soon as each page of
+// ListServiceQuotas arrives, so that large services, e.g. EC2, do not serialize the scan.
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::catalog::{Catalog, CatalogCache, QuotaDefinition};
+use crate::filter::Filter;
+use crate::quotas::{Quota, GLOBAL_REGION};
+use crate::scheduler::Scheduler;
+use crate::services::servicequota;
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    // prefer manual collectors over CloudWatch usage metrics
+    pub realtime: bool,
+    // evaluate the utilization of each quota, or only discover them
+    pub evaluate: bool,
+    // the number of services discovered concurrently, per region
+    pub discovery_concurrency: usize,
+    // the number of quotas evaluated concurrently, across all regions
+    pub evaluation_concurrency: usize,
+    // the cache of discovered quotas, if enabled
+    pub catalog: Option<CatalogCache>,
+    pub filter: Filter,
+}
+
+// global_region is the region global quotas are evaluated in, so that they are evaluated once
+// per account rather than once per region. us-east-1 is preferred, as it is where the usage
+// metrics of global services, e.g. IAM, are published.
+pub fn global_region(regions: &[String]) -> Option<&str> {
+    regions
+        .iter()
+        .find(|r| *r == "us-east-1")
+        .or_else(|| regions.first())
+        .map(|r| r.as_str())
+}
+
+#[derive(Default)]
+pub struct Scan {
+    pub quotas: Vec<Box<dyn Quota>>,
+    // the regions and services that could not be discovered
+    pub errors: Vec<String>,
+    // the regions where every service was discovered
+    pub regions_complete: Vec<String>,
+    // the regions where no services could be discovered
+    pub regions_failed: Vec<String>,
+    // the regions where Service Quotas is not available, with the reason
+    pub regions_skipped: Vec<(String, String)>,
+    // the region global quotas were sent from in full, the canonical region or, when it could
+    // not be discovered, the first region that could
+    pub global_region: Option<String>,
+}
+
+impl Scan {
+    // record adds the outcome of the discovery of a region
+    fn record(&mut self, region: &str, discovery: Result<Discovery, RegionError>) {
+        match discovery {
+            Ok(discovery) => {
+                if discovery.errors.is_empty() {
+                    self.regions_complete.push(region.to_string());
+                }
+                self.errors.extend(discovery.errors)
+            }
+            Err(RegionError::Failed(err)) => {
+                self.errors.push(err);
+                self.regions_failed.push(region.to_string());
+            }
+            Err(RegionError::Unavailable(region, reason)) => {
+                self.regions_skipped.push((region, reason))
+            }
+        }
+    }
+
+    // nothing_scanned is true when every region failed or was skipped, so that there is
+    // nothing to report on
+    pub fn nothing_scanned(&self, regions: &[String]) -> bool {
+        self.regions_failed.len() + self.regions_skipped.len() == regions.len()
+    }
+
+    // globals_from is the region to send global quotas from: the global region when it was
+    // discovered in full, otherwise the first region that was
+    fn globals_from<'a>(&self, regions: &'a [String]) -> Option<&'a str> {
+        let complete = |region: &&String| self.regions_complete.contains(region);
+
+        global_region(regions)
+            .filter(|region| self.regions_complete.iter().any(|r| r == region))
+            .or_else(|| regions.iter().find(complete).map(|r| r.as_str()))
+    }
+}
+
+// Discovery is the outcome of the discovery of a region
+#[derive(Debug, Default)]
+struct Discovery {
+    // the services that could not be discovered
+    errors: Vec<String>,
+    // the global quotas matching the filter, which are only sent from the global region, so
+    // that another region can send them when it could not be discovered
+    globals: Vec<QuotaDefinition>,
+}
+
+// RegionError is why the services of a region could not be discovered
+#[derive(Debug)]
+enum RegionError {
+    Failed(String),
+    // the region is skipped rather than reported as an error
+    Unavailable(String, String),
+}
+
+pub async fn scan(regions: &[String], scheduler: &Arc<Scheduler>, options: ScanOptions) -> Scan {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    let discoveries = regions
+        .iter()
+        .map(|region| {
+            let region = region.clone();
+            let scheduler = Arc::clone(scheduler);
+            let sender = sender.clone();
+            let options = options.clone();
+            let send_global = global_region(regions) == Some(region.as_str());
+
+            tokio::spawn(
+                async move { discover(&region, send_global, scheduler, options, sender).await },
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // the channel closes once every discovery has dropped its sender
+    drop(sender);
+
+    let mut result = Scan::default();
+    let permits = Arc::new(Semaphore::new(options.evaluation_concurrency.max(1)));
+    let mut evaluations = Vec::new();
+
+    while let Some(quota) = receiver.recv().await {
+        if !options.evaluate {
+            result.quotas.push(quota);
+            continue;
+        }
+
+        let permit = Arc::clone(&permits).acquire_owned().await.unwrap();
+        evaluations.push(tokio::spawn(async move {
+            // errors are cached on the quota, and reported once the scan completes
+            let _ = quota.utilization().await;
+            drop(permit);
+            quota
+        }));
+    }
+
+    for evaluation in evaluations {
+        match evaluation.await {
+            Ok(quota) => result.quotas.push(quota),
+            Err(err) => result.errors.push(err.to_string()),
+        }
+    }
+
+    let mut globals = Vec::new();
+    for (region, discovery) in regions.iter().zip(discoveries) {
+        let mut discovery = match discovery.await {
+            Ok(discovery) => discovery,
+            Err(err) => Err(RegionError::Failed(err.to_string())),
+        };
+        if let Ok(discovery) = &mut discovery {
+            globals.push((region.clone(), std::mem::take(&mut discovery.globals)));
+        }
+        result.record(region, discovery);
+    }
+
+    send_fallback_globals(regions, scheduler, &options, globals, &mut result).await;
+
+    result
+}
+
+// send_fallback_globals sends the global quotas from the first region discovered in full when
+// the global region could not be, so that they are not missing from the scan. Global quotas
+// already sent by the global region, before it failed, are not sent again.
+async fn send_fallback_globals(
+    regions: &[String],
+    scheduler: &Arc<Scheduler>,
+    options: &ScanOptions,
+    globals: Vec<(String, Vec<QuotaDefinition>)>,
+    result: &mut Scan,
+) {
+    let (global_region, region) = match (global_region(regions), result.globals_from(regions)) {
+        (Some(global_region), Some(region)) => (global_region, region),
+        (Some(global_region), None) => {
+            result.errors.push(format!(
+                "global quotas were not scanned, as region {} could not be discovered",
+                global_region
+            ));
+            return;
+        }
+        (None, _) => return,
+    };
+    result.global_region = Some(region.to_string());
+    if region == global_region {
+        return;
+    }
+
+    let definitions = globals
+        .into_iter()
+        .find(|(r, _)| r == region)
+        .map(|(_, definitions)| definitions)
+        .unwrap_or_default();
+
+    let mut sent = HashSet::new();
+    for quota in &result.quotas {
+        if quota.region().await == GLOBAL_REGION {
+            sent.insert((
+                quota.service_code().await.to_string(),
+                quota.quota_code().await.to_string(),
+            ));
+        }
+    }
+
+    println!("checking for global quotas in region {}", region);
+    let client = servicequota::Client::new(region, Arc::clone(scheduler)).await;
+
+    let definitions = definitions.iter().filter(|definition| {
+        !sent.contains(&(
+            definition.service_code.clone(),
+            definition.quota_code.clone(),
+        ))
+    });
+    for definition in definitions {
+        match client.new_quota(definition, options.realtime).await {
+            Ok(Some(quota)) => {
+                if options.evaluate {
+                    // errors are cached on the quota, and reported once the scan completes
+                    let _ = quota.utilization().await;
+                }
+                result.quotas.push(quota);
+            }
+            Ok(None) => {}
+            Err(err) => result.errors.push(format!(
+                "failed to create global quota {} in region {}: {}",
+                definition.arn, region, err
+            )),
+        }
+    }
+}
+
+// discover sends the quotas of every service in the region, returning the services that could
+// not be discovered, or an error if the services of the region could not be listed. Quotas are
+// sent from the catalog cache when it is fresh. Global quotas are only sent when send_global is
+// set, and are returned otherwise.
+async fn discover(
+    region: &str,
+    send_global: bool,
+    scheduler: Arc<Scheduler>,
+    options: ScanOptions,
+    sender: mpsc::UnboundedSender<Box<dyn Quota>>,
+) -> Result<Discovery, RegionError> {
+    println!("checking for quotas in region {}", region);
+
+    let client = servicequota::Client::new(region, scheduler).await;
+
+    if let Some(catalog) = options.catalog.as_ref().and_then(|c| c.load(region)) {
+        return Ok(Discovery {
+            errors: send_cached_quotas(&client, &catalog, send_global, &options, &sender).await,
+            globals: globals(&catalog.quotas, send_global, &options.filter),
+        });
+    }
+
+    // listing the services of the region is skipped when they are given explicitly, or when the
+    // services of the quotas given are known from the catalog cache, however old
+    let known_services = match (&options.filter.services, &options.filter.quotas) {
+        (None, Some(quota_codes)) => options
+            .catalog
+            .as_ref()
+            .and_then(|c| c.services(region, quota_codes)),
+        _ => None,
+    };
+    let pruned = known_services.is_some();
+
+    let service_codes = match (&options.filter.services, known_services) {
+        (Some(services), _) => services.clone(),
+        (None, Some(services)) => services,
+        (None, None) => match client.service_codes().await {
+            Ok(service_codes) => service_codes,
+            Err(err) if err.is_unreachable() && !has_endpoint(region).await => {
+                return Err(RegionError::Unavailable(
+                    region.to_string(),
+                    format!("Service Quotas is not available: {}", err),
+                ));
+            }
+            Err(err) => {
+                return Err(RegionError::Failed(format!(
+                    "failed to list services in region {}: {}",
+                    region, err
+                )));
+            }
+        },
+    };
+
+    let results = each_service(&client, &service_codes, &options, |client, service_code| {
+        let sender = sender.clone();
+        let filter = options.filter.clone();
+        let realtime = options.realtime;
+
+        async move {
+            client
+                .send_quotas(&service_code, realtime, &filter, send_global, &sender)
+                .await
+                .map_err(|err| {
+                    format!(
+                        "failed to list quotas for service {} in region {}: {}",
+                        service_code,
+                        client.region(),
+                        err
+                    )
+                })
+        }
+    })
+    .await;
+
+    let mut errors = Vec::new();
+    let mut definitions = Vec::new();
+    for result in results {
+        match result {
+            Ok(d) => definitions.extend(d),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    // only a complete catalog is cached, otherwise the missing services would be skipped until
+    // the cache expires
+    let globals = globals(&definitions, send_global, &options.filter);
+    let complete = errors.is_empty() && !options.filter.is_partial() && !pruned;
+    if let (Some(cache), true) = (&options.catalog, complete) {
+        if let Err(err) = cache.store(&Catalog::new(region, service_codes, definitions)) {
+            log::warn!("failed to cache catalog for region {}: {}", region, err);
+        }
+    }
+
+    Ok(Discovery { errors, globals })
+}
+
+// globals returns the global quotas of the definitions matching the filter, when they were not
+// sent
+fn globals(
+    definitions: &[QuotaDefinition],
+    send_global: bool,
+    filter: &Filter,
+) -> Vec<QuotaDefinition> {
+    match send_global {
+        true => Vec::new(),
+        false => definitions
+            .iter()
+            .filter(|definition| definition.global && filter.matches_quota(definition))
+            .cloned()
+            .collect(),
+    }
+}
+
+// each_service runs a discovery for each service matching the filter, with up to the discovery
+// concurrency of them at once, and returns their results in the order of the services. A
+// discovery that panics is returned as an error.
+pub async fn each_service<T, F, Fut>(
+    client: &servicequota::Client,
+    service_codes: &[String],
+    options: &ScanOptions,
+    discovery: F,
+) -> Vec<Result<T, String>>
+where
+    F: Fn(servicequota::Client, String) -> Fut,
+    Fut: Future<Output = Result<T, String>> + Send + 'static,
+    T: Send + 'static,
+{
+    let permits = Arc::new(Semaphore::new(options.discovery_concurrency.max(1)));
+
+    let handlers = service_codes
+        .iter()
+        .filter(|service_code| options.filter.matches_service(service_code))
+        .map(|service_code| {
+            let permits = Arc::clone(&permits);
+            let discovery = discovery(client.clone(), service_code.clone());
+
+            tokio::spawn(async move {
+                let _permit = permits.acquire().await.unwrap();
+                discovery.await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = Vec::new();
+    for handler in handlers {
+        match handler.await {
+            Ok(result) => results.push(result),
+            Err(err) => results.push(Err(err.to_string())),
+        }
+    }
+
+    results
+}
+
+// has_endpoint is false when the Service Quotas endpoint of the region does not resolve while
+// the EC2 endpoint of the region does, i.e. Service Quotas is not available in the region. An
+// outage of the network or DNS fails both, and is not mistaken for a region to skip.
+async fn has_endpoint(region: &str) -> bool {
+    let domain = match region.starts_with("cn-") {
+        true => "amazonaws.com.cn",
+        false => "amazonaws.com",
+    };
+    let resolves =
+        |service: &str| tokio::net::lookup_host(format!("{}.{}.{}:443", service, region, domain));
+
+    resolves("servicequotas").await.is_ok() || resolves("ec2").await.is_err()
+}
+
+// send_cached_quotas sends the quotas of the catalog, returning the quotas that could not be
+// created
+async fn send_cached_quotas(
+    client: &servicequota::Client,
+    catalog: &Catalog,
+    send_global: bool,
+    options: &ScanOptions,
+    sender: &mpsc::UnboundedSender<Box<dyn Quota>>,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let definitions = catalog
+        .quotas
+        .iter()
+        .filter(|definition| options.filter.matches_quota(definition))
+        .filter(|definition| send_global || !definition.global);
+
+    for definition in definitions {
+        match client.new_quota(definition, options.realtime).await {
+            Ok(Some(quota)) => {
+                let _ = sender.send(quota);
+            }
+            Ok(None) => {}
+            Err(err) => errors.push(format!(
+                "failed to create quota {} from the catalog: {}",
+                definition.arn, err
+            )),
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_global_region() {
+        let regions = |regions: &[&str]| regions.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            global_region(&regions(&["eu-west-1", "us-east-1"])),
+            Some("us-east-1")
+        );
+        assert_eq!(
+            global_region(&regions(&["eu-west-1", "eu-west-2"])),
+            Some("eu-west-1")
+        );
+        assert_eq!(global_region(&[]), None);
+    }
+
+    #[test]
+    fn test_nothing_scanned() {
+        let regions = vec!["us-east-1".to_string(), "eu-west-1".to_string()];
+
+        // a network outage fails every region rather than skipping them
+        let mut scan = Scan::default();
+        for region in &regions {
+            let err = format!(
+                "failed to list services in region {}: dispatch failure",
+                region
+            );
+            scan.record(region, Err(RegionError::Failed(err)));
+        }
+        assert!(scan.nothing_scanned(&regions));
+        assert_eq!(scan.regions_failed, regions);
+        assert!(scan.regions_skipped.is_empty());
+
+        let mut scan = Scan::default();
+        scan.record(
+            "us-east-1",
+            Err(RegionError::Unavailable(
+                "us-east-1".to_string(),
+                "Service Quotas is not available".to_string(),
+            )),
+        );
+        scan.record(
+            "eu-west-1",
+            Ok(Discovery {
+                errors: vec!["failed to list quotas".to_string()],
+                ..Default::default()
+            }),
+        );
+        assert!(!scan.nothing_scanned(&regions));
+    }
+
+    #[test]
+    fn test_globals_from() {
+        let regions = vec![
+            "eu-west-1".to_string(),
+            "us-east-1".to_string(),
+            "eu-west-2".to_string(),
+        ];
+        let scan = |complete: &[&str]| Scan {
+            regions_complete: complete.iter().map(|r| r.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            scan(&["eu-west-2", "us-east-1"]).globals_from(&regions),
+            Some("us-east-1")
+        );
+        // the first region discovered in full sends the global quotas when us-east-1 fails
+        assert_eq!(
+            scan(&["eu-west-2", "eu-west-1"]).globals_from(&regions),
+            Some("eu-west-1")
+        );
+        assert_eq!(scan(&[]).globals_from(&regions), None);
+    }
+}
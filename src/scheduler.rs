@@ -0,0 +1,460 @@
+// Scheduler rate limits the calls to each AWS API, per region, with a token bucket. When a call
+// is throttled the rate of the bucket is halved, and recovers as calls succeed.
+
+use aws_sdk_cloudwatch::types::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::util;
+
+// the number of times a throttled call is retried by the scheduler, once the retries of the
+// SDK are exhausted
+const MAX_THROTTLE_RETRIES: u32 = 3;
+
+// the slowest rate a bucket backs off to, in requests per second
+const MIN_RATE: f64 = 0.1;
+
+// Api identifies the AWS APIs that are rate limited independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Api {
+    ServiceQuotas,
+    CloudWatch,
+    Lambda,
+    Ec2,
+}
+
+impl Api {
+    // default_rate is the default number of requests per second, per region
+    fn default_rate(&self) -> f64 {
+        match self {
+            Self::ServiceQuotas => 5.0,
+            Self::CloudWatch => 20.0,
+            Self::Lambda => 10.0,
+            Self::Ec2 => 20.0,
+        }
+    }
+}
+
+impl Display for Api {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::ServiceQuotas => write!(f, "servicequotas"),
+            Self::CloudWatch => write!(f, "cloudwatch"),
+            Self::Lambda => write!(f, "lambda"),
+            Self::Ec2 => write!(f, "ec2"),
+        }
+    }
+}
+
+impl FromStr for Api {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "servicequotas" => Ok(Self::ServiceQuotas),
+            "cloudwatch" => Ok(Self::CloudWatch),
+            "lambda" => Ok(Self::Lambda),
+            "ec2" => Ok(Self::Ec2),
+            _ => Err(format!(
+                "unknown api: {}, expected one of servicequotas, cloudwatch, lambda, ec2",
+                s
+            )),
+        }
+    }
+}
+
+// parse_rate_limit parses a rate limit of the form api=rate, e.g. cloudwatch=10
+pub fn parse_rate_limit(s: &str) -> Result<(Api, f64), String> {
+    let (api, rate) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid rate limit: {}, expected api=rate", s))?;
+
+    let rate = rate
+        .parse::<f64>()
+        .map_err(|e| format!("invalid rate limit: {}: {}", s, e))?;
+
+    if rate <= 0.0 {
+        return Err(format!("invalid rate limit: {}, rate must be positive", s));
+    }
+
+    Ok((api.parse()?, rate))
+}
+
+// Throttles reports the number of throttled calls to an API in a region, and the rate the
+// bucket had backed off to at the end of the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Throttles {
+    pub api: Api,
+    pub region: String,
+    pub throttles: u64,
+    pub rate: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    rates: HashMap<Api, f64>,
+    buckets: Mutex<HashMap<(Api, String), Arc<TokenBucket>>>,
+}
+
+impl Scheduler {
+    // new creates a scheduler with the given rates, in requests per second. APIs without a rate
+    // use their default.
+    pub fn new(rates: HashMap<Api, f64>) -> Self {
+        Self {
+            rates,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn bucket(&self, api: Api, region: &str) -> Arc<TokenBucket> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let bucket = buckets.entry((api, region.to_string())).or_insert_with(|| {
+            let rate = self
+                .rates
+                .get(&api)
+                .copied()
+                .unwrap_or_else(|| api.default_rate());
+            Arc::new(TokenBucket::new(rate))
+        });
+
+        Arc::clone(bucket)
+    }
+
+    // call waits for a token from the bucket of the api and region, then makes the call.
+    // Throttled calls back off the bucket, and are retried.
+    pub async fn call<T, E, R, F, Fut>(
+        &self,
+        api: Api,
+        region: &str,
+        f: F,
+    ) -> Result<T, SdkError<E, R>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, R>>>,
+        E: ProvideErrorKind,
+    {
+        let bucket = self.bucket(api, region);
+        let mut retries = 0;
+
+        loop {
+            bucket.acquire().await;
+
+            let result = f().await;
+
+            match &result {
+                Err(err) if util::is_throttling_error_code(util::sdk_error_code(err)) => {
+                    bucket.throttled();
+
+                    if retries < MAX_THROTTLE_RETRIES {
+                        retries += 1;
+                        continue;
+                    }
+                }
+                _ => bucket.succeeded(),
+            }
+
+            return result;
+        }
+    }
+
+    // throttles returns the APIs and regions that were throttled, sorted by api and region
+    pub fn throttles(&self) -> Vec<Throttles> {
+        let buckets = self.buckets.lock().unwrap();
+
+        let mut throttles = buckets
+            .iter()
+            .filter_map(|((api, region), bucket)| {
+                let state = bucket.state.lock().unwrap();
+
+                (state.throttles > 0).then(|| Throttles {
+                    api: *api,
+                    region: region.clone(),
+                    throttles: state.throttles,
+                    rate: state.rate,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        throttles.sort_by(|a, b| (a.api, &a.region).cmp(&(b.api, &b.region)));
+        throttles
+    }
+
+    // pages paginates a call to the api in the region by hand, so that each page takes a token
+    pub fn pages<'a>(&'a self, api: Api, region: &'a str) -> Pages<'a> {
+        Pages {
+            scheduler: self,
+            api,
+            region,
+            next_token: None,
+            done: false,
+        }
+    }
+}
+
+// Pages requests the pages of a paginated call one at a time, through the scheduler.
+pub struct Pages<'a> {
+    scheduler: &'a Scheduler,
+    api: Api,
+    region: &'a str,
+    next_token: Option<String>,
+    done: bool,
+}
+
+impl Pages<'_> {
+    // next makes the call with the token of the next page, returning None once the last page
+    // was returned
+    pub async fn next<T, E, R, F, Fut>(
+        &mut self,
+        f: F,
+        next_token: fn(&T) -> Option<&str>,
+    ) -> Result<Option<T>, SdkError<E, R>>
+    where
+        F: Fn(Option<String>) -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, R>>>,
+        E: ProvideErrorKind,
+    {
+        if self.done {
+            return Ok(None);
+        }
+
+        let token = self.next_token.take();
+        let output = self
+            .scheduler
+            .call(self.api, self.region, || f(token.clone()))
+            .await?;
+
+        self.next_token = next_token(&output).map(|t| t.to_string());
+        self.done = self.next_token.is_none();
+
+        Ok(Some(output))
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    max_rate: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    // the current rate, in tokens per second
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    throttles: u64,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            max_rate: rate,
+            state: Mutex::new(BucketState {
+                rate,
+                tokens: burst(rate),
+                refilled_at: Instant::now(),
+                throttles: 0,
+            }),
+        }
+    }
+
+    async fn acquire(&self) {
+        loop {
+            let wait = self.state.lock().unwrap().take(Instant::now());
+
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+
+    // throttled halves the rate, and empties the bucket so that the retry waits for the next
+    // token rather than spending the burst
+    fn throttled(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.rate = (state.rate / 2.0).max(MIN_RATE);
+        state.tokens = 0.0;
+        state.refilled_at = Instant::now();
+        state.throttles += 1;
+    }
+
+    fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.rate = (state.rate + self.max_rate * 0.05).min(self.max_rate);
+    }
+}
+
+impl BucketState {
+    // take takes a token from the bucket, or returns how long to wait for the next token
+    fn take(&mut self, now: Instant) -> Option<Duration> {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(burst(self.rate));
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return None;
+        }
+
+        Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+    }
+}
+
+// burst is the capacity of a bucket, allowing up to a second of requests at once
+fn burst(rate: f64) -> f64 {
+    rate.max(1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate_limit() {
+        assert_eq!(
+            parse_rate_limit("cloudwatch=10"),
+            Ok((Api::CloudWatch, 10.0))
+        );
+        assert_eq!(
+            parse_rate_limit("servicequotas=0.5"),
+            Ok((Api::ServiceQuotas, 0.5))
+        );
+        assert!(parse_rate_limit("cloudwatch").is_err());
+        assert!(parse_rate_limit("cloudwatch=0").is_err());
+        assert!(parse_rate_limit("s3=10").is_err());
+    }
+
+    #[test]
+    fn test_bucket_take() {
+        let now = Instant::now();
+        let mut state = BucketState {
+            rate: 2.0,
+            tokens: 2.0,
+            refilled_at: now,
+            throttles: 0,
+        };
+
+        assert_eq!(state.take(now), None);
+        assert_eq!(state.take(now), None);
+        assert_eq!(state.take(now), Some(Duration::from_millis(500)));
+        assert_eq!(state.take(now + Duration::from_millis(500)), None);
+    }
+
+    #[test]
+    fn test_bucket_backoff() {
+        let bucket = TokenBucket::new(8.0);
+
+        bucket.throttled();
+        bucket.throttled();
+        assert_eq!(bucket.state.lock().unwrap().rate, 2.0);
+        assert_eq!(bucket.state.lock().unwrap().throttles, 2);
+
+        for _ in 0..100 {
+            bucket.succeeded();
+        }
+        assert_eq!(bucket.state.lock().unwrap().rate, 8.0);
+    }
+
+    #[test]
+    fn test_bucket_backoff_min_rate() {
+        let bucket = TokenBucket::new(0.1);
+
+        bucket.throttled();
+        assert_eq!(bucket.state.lock().unwrap().rate, MIN_RATE);
+    }
+
+    #[test]
+    fn test_scheduler_rates() {
+        let scheduler = Scheduler::new(HashMap::from([(Api::CloudWatch, 1.0)]));
+
+        let cloudwatch = scheduler.bucket(Api::CloudWatch, "us-east-1");
+        let servicequotas = scheduler.bucket(Api::ServiceQuotas, "us-east-1");
+
+        assert_eq!(cloudwatch.max_rate, 1.0);
+        assert_eq!(servicequotas.max_rate, Api::ServiceQuotas.default_rate());
+        assert!(Arc::ptr_eq(
+            &cloudwatch,
+            &scheduler.bucket(Api::CloudWatch, "us-east-1")
+        ));
+        assert!(!Arc::ptr_eq(
+            &cloudwatch,
+            &scheduler.bucket(Api::CloudWatch, "eu-west-1")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_throttled_call_waits() {
+        use aws_sdk_cloudwatch::error::GetMetricDataError;
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let scheduler = Scheduler::new(HashMap::from([(Api::CloudWatch, 20.0)]));
+        let calls = AtomicU32::new(0);
+        let started = Instant::now();
+
+        // the first call is throttled, with the burst of the bucket still available
+        let result = scheduler
+            .call(Api::CloudWatch, "us-east-1", || {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match call {
+                        0 => Err(SdkError::ServiceError {
+                            err: GetMetricDataError::generic(
+                                aws_smithy_types::Error::builder()
+                                    .code("Throttling")
+                                    .build(),
+                            ),
+                            raw: (),
+                        }),
+                        _ => Ok(call),
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 1);
+        // the retry waits for a token at the halved rate of 10 per second
+        assert!(started.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn test_pages() {
+        use aws_sdk_servicequotas::error::ListServicesError;
+
+        let scheduler = Scheduler::new(HashMap::new());
+        let mut pages = scheduler.pages(Api::ServiceQuotas, "us-east-1");
+
+        // each page is the token it was requested with, and the token of the next page
+        let mut tokens = Vec::new();
+        while let Some((token, _)) = pages
+            .next(
+                |token| {
+                    let next = match token.as_deref() {
+                        None => Some("2".to_string()),
+                        Some("2") => Some("3".to_string()),
+                        _ => None,
+                    };
+                    async move { Ok::<_, SdkError<ListServicesError>>((token, next)) }
+                },
+                |page| page.1.as_deref(),
+            )
+            .await
+            .unwrap()
+        {
+            tokens.push(token);
+        }
+
+        assert_eq!(
+            tokens,
+            vec![None, Some("2".to_string()), Some("3".to_string())]
+        );
+    }
+}
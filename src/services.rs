@@ -1,2 +1,6 @@
 pub mod cloudwatch;
+pub mod ec2;
+pub mod iam;
+pub mod organizations;
 pub mod servicequota;
+pub mod sts;
@@ -4,6 +4,7 @@ use aws_sdk_cloudwatch::error::GetMetricDataError;
 use chrono::{Duration, DurationRound, Utc};
 use std::collections::HashMap;
 
+use crate::scheduler::{Api, Scheduler};
 use crate::util;
 use aws_sdk_cloudwatch::{
     self,
@@ -12,12 +13,12 @@ use aws_sdk_cloudwatch::{
 };
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use tokio_stream::StreamExt;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub enum CloudWatchError {
     MissingMetricData,
-    AwsCloudWatchSdkError(SdkError<GetMetricDataError>),
+    AwsCloudWatchSdkError(Box<SdkError<GetMetricDataError>>),
 }
 
 impl Error for CloudWatchError {}
@@ -32,13 +33,15 @@ impl Display for CloudWatchError {
 
 impl From<SdkError<GetMetricDataError>> for CloudWatchError {
     fn from(err: SdkError<GetMetricDataError>) -> Self {
-        Self::AwsCloudWatchSdkError(err)
+        Self::AwsCloudWatchSdkError(Box::new(err))
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Client {
     client: aws_sdk_cloudwatch::Client,
+    region: String,
+    scheduler: Arc<Scheduler>,
 }
 
 #[derive(Debug, Clone)]
@@ -47,23 +50,39 @@ pub struct ServiceQuotaUtilizationQueryInput {
     pub metric_name: String,
     pub dimensions: HashMap<String, String>,
     pub statistic: String,
+    // the applied quota value from ListServiceQuotas, used when SERVICE_QUOTA() yields no data
+    pub applied_value: Option<f64>,
+}
+
+// MetricUtilization is the utilization percentage calculated from the usage metrics, and
+// whether it was calculated from the raw usage rather than SERVICE_QUOTA() metric math.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricUtilization {
+    pub percentage: u8,
+    pub fallback: bool,
+    pub usage: Option<f64>,
+    pub limit: Option<f64>,
 }
 
 impl Client {
-    pub async fn new(region: &str) -> Self {
+    pub async fn new(region: &str, scheduler: Arc<Scheduler>) -> Self {
         let (config, retries) = util::aws_config_with_region(region).await;
         let client_config = aws_sdk_cloudwatch::config::Builder::from(&config)
             .retry_config(retries)
             .build();
         let client = aws_sdk_cloudwatch::Client::from_conf(client_config);
 
-        Self { client }
+        Self {
+            client,
+            region: region.to_string(),
+            scheduler,
+        }
     }
 
     pub async fn service_quota_utilization(
         self,
         query_input: &ServiceQuotaUtilizationQueryInput,
-    ) -> Result<u8, CloudWatchError> {
+    ) -> Result<MetricUtilization, CloudWatchError> {
         let (start_time, end_time) = query_times();
 
         let dimensions = hashmap_to_dimensions(&query_input.dimensions);
@@ -83,7 +102,7 @@ impl Client {
         let usage_data = MetricDataQuery::builder()
             .metric_stat(metric_stat)
             .id("usage_data")
-            .return_data(false)
+            .return_data(true)
             .build();
 
         let percentage_usage_data = MetricDataQuery::builder()
@@ -92,31 +111,53 @@ impl Client {
             .return_data(true)
             .build();
 
-        let results = self
-            .client
-            .get_metric_data()
-            .metric_data_queries(usage_data)
-            .metric_data_queries(percentage_usage_data)
-            .start_time(DateTime::from_secs(start_time as i64))
-            .end_time(DateTime::from_secs(end_time as i64))
-            .into_paginator()
-            .send()
-            .collect::<Result<Vec<_>, _>>()
-            .await?;
-
-        let mut max_value = None;
-        for result in results {
-            let r = result.metric_data_results().unwrap();
-
-            if let Some(value) = get_max_value(r) {
-                if Some(value) > max_value {
-                    max_value = Some(value);
-                }
-            }
+        let mut max_utilization = None;
+        let mut max_usage = None;
+        let mut pages = self.scheduler.pages(Api::CloudWatch, &self.region);
+
+        // each page takes a token of the scheduler, rather than one for the whole query
+        while let Some(output) = pages
+            .next(
+                |token| {
+                    self.client
+                        .get_metric_data()
+                        .metric_data_queries(usage_data.clone())
+                        .metric_data_queries(percentage_usage_data.clone())
+                        .start_time(DateTime::from_secs(start_time as i64))
+                        .end_time(DateTime::from_secs(end_time as i64))
+                        .set_next_token(token)
+                        .send()
+                },
+                |output| output.next_token(),
+            )
+            .await?
+        {
+            let r = output.metric_data_results().unwrap_or_default();
+
+            max_utilization = max(max_utilization, get_max_value(r, "utilization"));
+            max_usage = max(max_usage, get_max_value(r, "usage_data"));
+        }
+
+        if let Some(utilization) = max_utilization {
+            return Ok(MetricUtilization {
+                percentage: utilization as u8,
+                fallback: false,
+                usage: max_usage,
+                limit: query_input.applied_value,
+            });
         }
 
-        max_value
-            .map(|v| v as u8)
+        // SERVICE_QUOTA() often yields nothing even though the usage series exists, so fall back
+        // to dividing the raw usage by the applied quota value.
+        max_usage
+            .zip(query_input.applied_value)
+            .and_then(|(usage, applied_value)| fallback_utilization(usage, applied_value))
+            .map(|percentage| MetricUtilization {
+                percentage,
+                fallback: true,
+                usage: max_usage,
+                limit: query_input.applied_value,
+            })
             .ok_or(CloudWatchError::MissingMetricData)
     }
 }
@@ -134,21 +175,40 @@ fn query_times() -> (u64, u64) {
     (start_time, end_time)
 }
 
-fn get_max_value(metric_data_results: &[MetricDataResult]) -> Option<u8> {
+// get_max_value returns the maximum datapoint of the query with the given id
+fn get_max_value(metric_data_results: &[MetricDataResult], id: &str) -> Option<f64> {
     // TODO: max datapoints is 1
-    let mut max: Option<u8> = None;
+    let mut max_value: Option<f64> = None;
 
     for metric_data_result in metric_data_results {
+        if metric_data_result.id() != Some(id) {
+            continue;
+        }
+
         if let Some(values) = metric_data_result.values() {
             for value in values {
-                if Some(*value as u8) > max {
-                    max = Some(*value as u8)
-                }
+                max_value = max(max_value, Some(*value));
             }
         }
     }
 
-    max
+    max_value
+}
+
+fn max(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn fallback_utilization(usage: f64, applied_value: f64) -> Option<u8> {
+    if applied_value <= 0.0 {
+        return None;
+    }
+
+    Some((usage / applied_value * 100.0) as u8)
 }
 
 fn hashmap_to_dimensions(hashmap: &HashMap<String, String>) -> Option<Vec<Dimension>> {
@@ -156,9 +216,13 @@ fn hashmap_to_dimensions(hashmap: &HashMap<String, String>) -> Option<Vec<Dimens
         return None;
     }
 
+    // sort the dimensions so that the query is deterministic
+    let mut dimensions = hashmap.iter().collect::<Vec<_>>();
+    dimensions.sort();
+
     Some(
-        hashmap
-            .iter()
+        dimensions
+            .into_iter()
             .map(|(k, v)| Dimension::builder().name(k).value(v).build())
             .collect::<Vec<_>>(),
     )
@@ -168,6 +232,33 @@ fn hashmap_to_dimensions(hashmap: &HashMap<String, String>) -> Option<Vec<Dimens
 mod test {
     use super::*;
 
+    #[test]
+    fn test_get_max_value() {
+        let results = vec![
+            MetricDataResult::builder()
+                .id("usage_data")
+                .values(400.0)
+                .values(512.0)
+                .build(),
+            MetricDataResult::builder()
+                .id("utilization")
+                .values(12.0)
+                .values(40.5)
+                .build(),
+        ];
+
+        assert_eq!(get_max_value(&results, "usage_data"), Some(512.0));
+        assert_eq!(get_max_value(&results, "utilization"), Some(40.5));
+        assert_eq!(get_max_value(&results, "missing"), None);
+    }
+
+    #[test]
+    fn test_fallback_utilization() {
+        assert_eq!(fallback_utilization(5.0, 5.0), Some(100));
+        assert_eq!(fallback_utilization(384.0, 512.0), Some(75));
+        assert_eq!(fallback_utilization(1.0, 0.0), None);
+    }
+
     #[test]
     fn test_hashmap_to_dimension_none() {
         let hashmap = HashMap::new();
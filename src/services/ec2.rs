@@ -0,0 +1,82 @@
+// EC2 service APIs for discovering the regions of the account
+
+use aws_sdk_ec2::{self, error::DescribeRegionsError, types::SdkError};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+use crate::scheduler::{Api, Scheduler};
+use crate::util;
+
+#[derive(Debug)]
+pub enum Ec2Error {
+    AwsEc2SdkError(Box<SdkError<DescribeRegionsError>>),
+}
+
+impl Error for Ec2Error {}
+impl Display for Ec2Error {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::AwsEc2SdkError(e) => write!(f, "AwsEc2SdkError: {}", e),
+        }
+    }
+}
+
+impl From<SdkError<DescribeRegionsError>> for Ec2Error {
+    fn from(err: SdkError<DescribeRegionsError>) -> Self {
+        Self::AwsEc2SdkError(Box::new(err))
+    }
+}
+
+// RegionStatus is a region of the partition, and whether it is enabled for the account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionStatus {
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    client: aws_sdk_ec2::Client,
+    region: String,
+    scheduler: Arc<Scheduler>,
+}
+
+impl Client {
+    pub async fn new(region: &str, scheduler: Arc<Scheduler>) -> Self {
+        let (config, retries) = util::aws_config_with_region(region).await;
+        let client_config = aws_sdk_ec2::config::Builder::from(&config)
+            .retry_config(retries)
+            .build();
+        let client = aws_sdk_ec2::Client::from_conf(client_config);
+
+        Self {
+            client,
+            region: region.to_string(),
+            scheduler,
+        }
+    }
+
+    // regions returns every region of the partition, including the opt-in regions that are not
+    // enabled for the account
+    pub async fn regions(&self) -> Result<Vec<RegionStatus>, Ec2Error> {
+        let output = self
+            .scheduler
+            .call(Api::Ec2, &self.region, || {
+                self.client.describe_regions().all_regions(true).send()
+            })
+            .await?;
+
+        Ok(output
+            .regions()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|region| {
+                Some(RegionStatus {
+                    name: region.region_name()?.to_string(),
+                    enabled: region.opt_in_status() != Some("not-opted-in"),
+                })
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,55 @@
+// IAM service APIs for the alias of the account of the credentials
+
+use aws_sdk_iam::{self, error::ListAccountAliasesError, types::SdkError};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::util;
+
+#[derive(Debug)]
+pub enum IamError {
+    AwsIamSdkError(Box<SdkError<ListAccountAliasesError>>),
+}
+
+impl Error for IamError {}
+impl Display for IamError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::AwsIamSdkError(e) => write!(f, "AwsIamSdkError: {}", e),
+        }
+    }
+}
+
+impl From<SdkError<ListAccountAliasesError>> for IamError {
+    fn from(err: SdkError<ListAccountAliasesError>) -> Self {
+        Self::AwsIamSdkError(Box::new(err))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    client: aws_sdk_iam::Client,
+}
+
+impl Client {
+    pub async fn new(region: &str) -> Self {
+        let (config, retries) = util::aws_config_with_region(region).await;
+        let client_config = aws_sdk_iam::config::Builder::from(&config)
+            .retry_config(retries)
+            .build();
+        let client = aws_sdk_iam::Client::from_conf(client_config);
+
+        Self { client }
+    }
+
+    // account_alias returns the alias of the account the credentials belong to, an account has
+    // at most one alias
+    pub async fn account_alias(&self) -> Result<Option<String>, IamError> {
+        let output = self.client.list_account_aliases().send().await?;
+
+        Ok(output
+            .account_aliases()
+            .and_then(|aliases| aliases.first())
+            .map(|alias| alias.to_string()))
+    }
+}
@@ -0,0 +1,111 @@
+// Organizations service APIs for the tags and names of the accounts of an organization
+
+use aws_sdk_organizations::{
+    self,
+    error::{DescribeAccountError, ListTagsForResourceError},
+    types::SdkError,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::util;
+
+#[derive(Debug)]
+pub enum OrganizationsError {
+    AwsOrganizationsSdkErrorListTagsForResource(Box<SdkError<ListTagsForResourceError>>),
+    AwsOrganizationsSdkErrorDescribeAccount(Box<SdkError<DescribeAccountError>>),
+}
+
+impl Error for OrganizationsError {}
+impl Display for OrganizationsError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::AwsOrganizationsSdkErrorListTagsForResource(e) => {
+                write!(f, "AwsOrganizationsSdkErrorListTagsForResource: {}", e)
+            }
+            Self::AwsOrganizationsSdkErrorDescribeAccount(e) => {
+                write!(f, "AwsOrganizationsSdkErrorDescribeAccount: {}", e)
+            }
+        }
+    }
+}
+
+impl From<SdkError<ListTagsForResourceError>> for OrganizationsError {
+    fn from(err: SdkError<ListTagsForResourceError>) -> Self {
+        Self::AwsOrganizationsSdkErrorListTagsForResource(Box::new(err))
+    }
+}
+
+impl From<SdkError<DescribeAccountError>> for OrganizationsError {
+    fn from(err: SdkError<DescribeAccountError>) -> Self {
+        Self::AwsOrganizationsSdkErrorDescribeAccount(Box::new(err))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    client: aws_sdk_organizations::Client,
+}
+
+impl Client {
+    pub async fn new(region: &str) -> Self {
+        let (config, retries) = util::aws_config_with_region(region).await;
+        let client_config = aws_sdk_organizations::config::Builder::from(&config)
+            .retry_config(retries)
+            .build();
+        let client = aws_sdk_organizations::Client::from_conf(client_config);
+
+        Self { client }
+    }
+
+    // account_tags returns the tags of an account, which requires the credentials of the
+    // management account, or of a delegated administrator, of the organization
+    pub async fn account_tags(
+        &self,
+        account_id: &str,
+    ) -> Result<HashMap<String, String>, OrganizationsError> {
+        let mut tags = HashMap::new();
+        let mut next_token = None;
+
+        loop {
+            let output = self
+                .client
+                .list_tags_for_resource()
+                .resource_id(account_id)
+                .set_next_token(next_token)
+                .send()
+                .await?;
+
+            for tag in output.tags().unwrap_or_default() {
+                if let (Some(key), Some(value)) = (tag.key(), tag.value()) {
+                    tags.insert(key.to_string(), value.to_string());
+                }
+            }
+
+            next_token = output.next_token().map(|t| t.to_string());
+            if next_token.is_none() {
+                return Ok(tags);
+            }
+        }
+    }
+
+    // account_name returns the name of an account of the organization, with the same
+    // credentials as account_tags
+    pub async fn account_name(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<String>, OrganizationsError> {
+        let output = self
+            .client
+            .describe_account()
+            .account_id(account_id)
+            .send()
+            .await?;
+
+        Ok(output
+            .account()
+            .and_then(|account| account.name())
+            .map(|name| name.to_string()))
+    }
+}
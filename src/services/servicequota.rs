@@ -1,20 +1,34 @@
+use crate::catalog::QuotaDefinition;
+use crate::filter::Filter;
 use crate::quotas::{
-    lambda::QuotaL2ACBD22F, CloudWatchQuotaDetails, Quota, QuotaCloudWatch, QuotaError,
+    ec2::QuotaL0263D0A3, lambda::QuotaL2ACBD22F, CloudWatchQuotaDetails, GlobalQuota, Quota,
+    QuotaCloudWatch, QuotaError,
 };
+use crate::scheduler::{Api, Scheduler};
 use crate::services::cloudwatch;
 use crate::util;
 
 use aws_sdk_cloudwatch::types::SdkError;
-use aws_sdk_servicequotas::error::{ListServiceQuotasError, ListServicesError};
+use aws_sdk_servicequotas::error::{
+    ListAWSDefaultServiceQuotasError, ListRequestedServiceQuotaChangeHistoryByQuotaError,
+    ListServiceQuotasError, ListServicesError, RequestServiceQuotaIncreaseError,
+};
+use aws_sdk_servicequotas::model::RequestStatus;
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use tokio_stream::StreamExt;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
 
 #[derive(Debug)]
 pub enum ServiceQuotaError {
     QuotaError(QuotaError),
     AwsServiceQuotasSdkErrorListServiceQuotas(SdkError<ListServiceQuotasError>),
     AwsServiceQuotasSdkErrorListServices(SdkError<ListServicesError>),
+    AwsServiceQuotasSdkErrorListAWSDefaultServiceQuotas(SdkError<ListAWSDefaultServiceQuotasError>),
+    AwsServiceQuotasSdkErrorListRequestedServiceQuotaChangeHistoryByQuota(
+        SdkError<ListRequestedServiceQuotaChangeHistoryByQuotaError>,
+    ),
+    AwsServiceQuotasSdkErrorRequestServiceQuotaIncrease(SdkError<RequestServiceQuotaIncreaseError>),
 }
 
 impl Error for ServiceQuotaError {}
@@ -28,10 +42,43 @@ impl Display for ServiceQuotaError {
             Self::AwsServiceQuotasSdkErrorListServices(e) => {
                 write!(f, "AwsServiceQuotasSdkErrorListServices: {}", e)
             }
+            Self::AwsServiceQuotasSdkErrorListAWSDefaultServiceQuotas(e) => {
+                write!(
+                    f,
+                    "AwsServiceQuotasSdkErrorListAWSDefaultServiceQuotas: {}",
+                    e
+                )
+            }
+            Self::AwsServiceQuotasSdkErrorListRequestedServiceQuotaChangeHistoryByQuota(e) => {
+                write!(
+                    f,
+                    "AwsServiceQuotasSdkErrorListRequestedServiceQuotaChangeHistoryByQuota: {}",
+                    e
+                )
+            }
+            Self::AwsServiceQuotasSdkErrorRequestServiceQuotaIncrease(e) => {
+                write!(
+                    f,
+                    "AwsServiceQuotasSdkErrorRequestServiceQuotaIncrease: {}",
+                    e
+                )
+            }
         }
     }
 }
 
+impl ServiceQuotaError {
+    // is_unreachable is true when the Service Quotas endpoint of the region could not be
+    // reached, either because the service is not available in the region, or because of an
+    // outage of the network
+    pub fn is_unreachable(&self) -> bool {
+        matches!(
+            self,
+            Self::AwsServiceQuotasSdkErrorListServices(SdkError::DispatchFailure(err)) if err.is_io()
+        )
+    }
+}
+
 impl From<QuotaError> for ServiceQuotaError {
     fn from(err: QuotaError) -> Self {
         Self::QuotaError(err)
@@ -47,105 +94,340 @@ impl From<SdkError<ListServicesError>> for ServiceQuotaError {
         Self::AwsServiceQuotasSdkErrorListServices(err)
     }
 }
+impl From<SdkError<ListAWSDefaultServiceQuotasError>> for ServiceQuotaError {
+    fn from(err: SdkError<ListAWSDefaultServiceQuotasError>) -> Self {
+        Self::AwsServiceQuotasSdkErrorListAWSDefaultServiceQuotas(err)
+    }
+}
+impl From<SdkError<ListRequestedServiceQuotaChangeHistoryByQuotaError>> for ServiceQuotaError {
+    fn from(err: SdkError<ListRequestedServiceQuotaChangeHistoryByQuotaError>) -> Self {
+        Self::AwsServiceQuotasSdkErrorListRequestedServiceQuotaChangeHistoryByQuota(err)
+    }
+}
+impl From<SdkError<RequestServiceQuotaIncreaseError>> for ServiceQuotaError {
+    fn from(err: SdkError<RequestServiceQuotaIncreaseError>) -> Self {
+        Self::AwsServiceQuotasSdkErrorRequestServiceQuotaIncrease(err)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Client {
     client: aws_sdk_servicequotas::Client,
     cloudwatch_client: cloudwatch::Client,
+    region: String,
+    scheduler: Arc<Scheduler>,
 }
 
 impl Client {
-    pub async fn new(region: &str) -> Self {
+    pub async fn new(region: &str, scheduler: Arc<Scheduler>) -> Self {
         let (config, retries) = util::aws_config_with_region(region).await;
         let client_config = aws_sdk_servicequotas::config::Builder::from(&config)
             .retry_config(retries)
             .build();
         let client = aws_sdk_servicequotas::Client::from_conf(client_config);
 
-        let cloudwatch_client = cloudwatch::Client::new(region).await;
+        let cloudwatch_client = cloudwatch::Client::new(region, Arc::clone(&scheduler)).await;
 
         Self {
             client,
             cloudwatch_client,
+            region: region.to_string(),
+            scheduler,
         }
     }
 
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
     pub async fn service_codes(&self) -> Result<Vec<String>, ServiceQuotaError> {
-        let result = self
-            .client
-            .list_services()
-            .into_paginator()
-            .items()
-            .send()
-            .collect::<Result<Vec<_>, _>>()
-            .await?;
+        let mut service_codes = Vec::new();
+        let mut pages = self.scheduler.pages(Api::ServiceQuotas, &self.region);
+
+        while let Some(output) = pages
+            .next(
+                |token| self.client.list_services().set_next_token(token).send(),
+                |output| output.next_token(),
+            )
+            .await?
+        {
+            service_codes.extend(
+                output
+                    .services()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|s| s.service_code().unwrap().to_string()),
+            );
+        }
+
+        Ok(service_codes)
+    }
+
+    // send_quotas discovers the quotas for a service, sending each page of quotas as soon as it
+    // arrives so that they can be evaluated while discovery continues. Only the quotas matching
+    // the filter are sent, and global quotas only when send_global is set, but the definitions
+    // of every quota discovered are returned for the catalog.
+    pub async fn send_quotas(
+        &self,
+        service_code: &str,
+        realtime: bool,
+        filter: &Filter,
+        send_global: bool,
+        sender: &UnboundedSender<Box<dyn Quota>>,
+    ) -> Result<Vec<QuotaDefinition>, ServiceQuotaError> {
+        let mut definitions = Vec::new();
+        let mut pages = self.scheduler.pages(Api::ServiceQuotas, &self.region);
+
+        while let Some(output) = pages
+            .next(
+                |token| {
+                    self.client
+                        .list_service_quotas()
+                        .service_code(service_code)
+                        .set_next_token(token)
+                        .send()
+                },
+                |output| output.next_token(),
+            )
+            .await?
+        {
+            for quota in output.quotas().unwrap_or_default() {
+                let definition = QuotaDefinition::from(quota);
+
+                if filter.matches_quota(&definition) && (send_global || !definition.global) {
+                    if let Some(quota) = self.new_quota(&definition, realtime).await? {
+                        // the receiver has gone away, so there is no one left to send quotas to
+                        let _ = sender.send(quota);
+                    }
+                }
+
+                definitions.push(definition);
+            }
+        }
+
+        Ok(definitions)
+    }
+
+    // applied_quotas returns the quotas of a service with the values applied to the account
+    pub async fn applied_quotas(
+        &self,
+        service_code: &str,
+    ) -> Result<Vec<QuotaDefinition>, ServiceQuotaError> {
+        let mut definitions = Vec::new();
+        let mut pages = self.scheduler.pages(Api::ServiceQuotas, &self.region);
+
+        while let Some(output) = pages
+            .next(
+                |token| {
+                    self.client
+                        .list_service_quotas()
+                        .service_code(service_code)
+                        .set_next_token(token)
+                        .send()
+                },
+                |output| output.next_token(),
+            )
+            .await?
+        {
+            definitions.extend(
+                output
+                    .quotas()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(QuotaDefinition::from),
+            );
+        }
+
+        Ok(definitions)
+    }
+
+    // default_quotas returns the quotas of a service with the default values set by AWS
+    pub async fn default_quotas(
+        &self,
+        service_code: &str,
+    ) -> Result<Vec<QuotaDefinition>, ServiceQuotaError> {
+        let mut definitions = Vec::new();
+        let mut pages = self.scheduler.pages(Api::ServiceQuotas, &self.region);
+
+        while let Some(output) = pages
+            .next(
+                |token| {
+                    self.client
+                        .list_aws_default_service_quotas()
+                        .service_code(service_code)
+                        .set_next_token(token)
+                        .send()
+                },
+                |output| output.next_token(),
+            )
+            .await?
+        {
+            definitions.extend(
+                output
+                    .quotas()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(QuotaDefinition::from),
+            );
+        }
+
+        Ok(definitions)
+    }
+
+    // open_increase_request returns the desired value of an increase request for the quota that
+    // is still pending or has a support case open
+    pub async fn open_increase_request(
+        &self,
+        service_code: &str,
+        quota_code: &str,
+    ) -> Result<Option<f64>, ServiceQuotaError> {
+        let mut pages = self.scheduler.pages(Api::ServiceQuotas, &self.region);
+
+        while let Some(output) = pages
+            .next(
+                |token| {
+                    self.client
+                        .list_requested_service_quota_change_history_by_quota()
+                        .service_code(service_code)
+                        .quota_code(quota_code)
+                        .set_next_token(token)
+                        .send()
+                },
+                |output| output.next_token(),
+            )
+            .await?
+        {
+            let open = output
+                .requested_quotas()
+                .unwrap_or_default()
+                .iter()
+                .find(|request| {
+                    matches!(
+                        request.status(),
+                        Some(RequestStatus::Pending | RequestStatus::CaseOpened)
+                    )
+                });
 
-        Ok(result
-            .into_iter()
-            .map(|s| s.service_code().unwrap().to_string())
-            .collect::<Vec<_>>())
+            if let Some(request) = open {
+                return Ok(request.desired_value());
+            }
+        }
+
+        Ok(None)
     }
 
-    pub async fn quotas(
+    // request_increase files a request to increase the quota to the desired value, returning the
+    // id of the request
+    pub async fn request_increase(
         &self,
         service_code: &str,
-    ) -> Result<Vec<Box<dyn Quota>>, ServiceQuotaError> {
-        let paginator = self
-            .client
-            .list_service_quotas()
-            .service_code(service_code)
-            .into_paginator()
-            .items()
-            .send();
-
-        let all_quotas = paginator.collect::<Result<Vec<_>, _>>().await?;
-
-        let mut quotas: Vec<Box<dyn Quota>> = Vec::new();
-        for quota in all_quotas {
-            let cw = self.cloudwatch_client.clone();
-
-            if let Some(usage_metric) = quota.usage_metric() {
+        quota_code: &str,
+        desired_value: f64,
+    ) -> Result<String, ServiceQuotaError> {
+        let output = self
+            .scheduler
+            .call(Api::ServiceQuotas, &self.region, || {
+                self.client
+                    .request_service_quota_increase()
+                    .service_code(service_code)
+                    .quota_code(quota_code)
+                    .desired_value(desired_value)
+                    .send()
+            })
+            .await?;
+
+        Ok(output
+            .requested_quota()
+            .and_then(|request| request.id())
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    // new_quota creates a quota from its definition. In realtime mode a manual collector, which
+    // counts resources through the service's own API, is preferred over the CloudWatch usage
+    // metrics. Quotas with neither are not supported. Global quotas are reported with the
+    // region "global".
+    pub async fn new_quota(
+        &self,
+        definition: &QuotaDefinition,
+        realtime: bool,
+    ) -> Result<Option<Box<dyn Quota>>, ServiceQuotaError> {
+        let quota = self.new_regional_quota(definition, realtime).await?;
+
+        Ok(match definition.global {
+            true => quota.map(|quota| Box::new(GlobalQuota::new(quota)) as Box<dyn Quota>),
+            false => quota,
+        })
+    }
+
+    async fn new_regional_quota(
+        &self,
+        definition: &QuotaDefinition,
+        realtime: bool,
+    ) -> Result<Option<Box<dyn Quota>>, ServiceQuotaError> {
+        let quota_code = definition.quota_code.as_str();
+        let arn = definition.arn.as_str();
+        let name = definition.name.as_str();
+        let value = definition.applied_value;
+
+        if realtime {
+            if let Some(quota_result) =
+                lookup_quota(quota_code, arn, name, value, &self.scheduler).await
+            {
+                return Ok(Some(quota_result));
+            }
+        }
+
+        match &definition.usage_metric {
+            Some(usage_metric) => {
                 let query_input = cloudwatch::ServiceQuotaUtilizationQueryInput {
-                    namespace: usage_metric.metric_namespace().unwrap().to_string(),
-                    metric_name: usage_metric.metric_name().unwrap().to_string(),
-                    dimensions: usage_metric.metric_dimensions().unwrap().clone(),
-                    statistic: usage_metric
-                        .metric_statistic_recommendation()
-                        .unwrap()
-                        .to_string(),
+                    namespace: usage_metric.namespace.clone(),
+                    metric_name: usage_metric.metric_name.clone(),
+                    dimensions: usage_metric.dimensions.clone(),
+                    statistic: usage_metric.statistic.clone(),
+                    applied_value: value,
                 };
 
                 let new_quota = QuotaCloudWatch::new(
-                    quota.quota_arn().unwrap(),
-                    quota.quota_name().unwrap(),
+                    arn,
+                    name,
                     Some(CloudWatchQuotaDetails {
-                        client: cw,
+                        client: self.cloudwatch_client.clone(),
                         query: query_input,
                     }),
                 )?;
 
-                quotas.push(Box::new(new_quota));
-                continue;
-            } else {
-                let quota_code = quota.quota_code().unwrap();
-                let arn = quota.quota_arn().unwrap();
-                let name = quota.quota_name().unwrap();
-
-                if let Some(quota_result) = lookup_quota(quota_code, arn, name).await {
-                    quotas.push(quota_result);
-                }
+                Ok(Some(Box::new(new_quota)))
+            }
+            None if !realtime => {
+                Ok(lookup_quota(quota_code, arn, name, value, &self.scheduler).await)
             }
+            None => Ok(None),
         }
-
-        Ok(quotas)
     }
 }
 
 // lookup_quota provides a lookup table for Quotas that are not supported by the CloudWatch API,
-// i.e. manually implemented quotas.
-async fn lookup_quota(quota_code: &str, arn: &str, name: &str) -> Option<Box<dyn Quota>> {
-    match quota_code {
-        "L-2ACBD22F" => Some(Box::new(QuotaL2ACBD22F::new(arn, name).await.unwrap())),
+// i.e. manually implemented quotas. The applied value is passed for collectors which can only
+// count the resources in use, and not look up the limit.
+async fn lookup_quota(
+    quota_code: &str,
+    arn: &str,
+    name: &str,
+    value: Option<f64>,
+    scheduler: &Arc<Scheduler>,
+) -> Option<Box<dyn Quota>> {
+    let scheduler = Arc::clone(scheduler);
+
+    match (quota_code, value) {
+        ("L-2ACBD22F", _) => Some(Box::new(
+            QuotaL2ACBD22F::new(arn, name, scheduler).await.unwrap(),
+        )),
+        ("L-0263D0A3", Some(value)) => Some(Box::new(
+            QuotaL0263D0A3::new(arn, name, value, scheduler)
+                .await
+                .unwrap(),
+        )),
         _ => None,
     }
 }
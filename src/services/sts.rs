@@ -0,0 +1,56 @@
+// STS service APIs for identifying the account of the credentials
+
+use aws_sdk_sts::{self, error::GetCallerIdentityError, types::SdkError};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::util;
+
+#[derive(Debug)]
+pub enum StsError {
+    MissingAccountId,
+    AwsStsSdkError(Box<SdkError<GetCallerIdentityError>>),
+}
+
+impl Error for StsError {}
+impl Display for StsError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::MissingAccountId => write!(f, "MissingAccountId"),
+            Self::AwsStsSdkError(e) => write!(f, "AwsStsSdkError: {}", e),
+        }
+    }
+}
+
+impl From<SdkError<GetCallerIdentityError>> for StsError {
+    fn from(err: SdkError<GetCallerIdentityError>) -> Self {
+        Self::AwsStsSdkError(Box::new(err))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    client: aws_sdk_sts::Client,
+}
+
+impl Client {
+    pub async fn new(region: &str) -> Self {
+        let (config, retries) = util::aws_config_with_region(region).await;
+        let client_config = aws_sdk_sts::config::Builder::from(&config)
+            .retry_config(retries)
+            .build();
+        let client = aws_sdk_sts::Client::from_conf(client_config);
+
+        Self { client }
+    }
+
+    // account_id returns the id of the account the credentials belong to
+    pub async fn account_id(&self) -> Result<String, StsError> {
+        let output = self.client.get_caller_identity().send().await?;
+
+        output
+            .account()
+            .map(|a| a.to_string())
+            .ok_or(StsError::MissingAccountId)
+    }
+}
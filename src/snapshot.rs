@@ -0,0 +1,307 @@
+// Snapshot records the full result of a scan in a versioned JSON file, so that two scans can be
+// compared, e.g. to confirm an approved increase took effect, or to review week-over-week change.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::Path;
+
+use crate::metadata::{Metadata, QuotaMetadata};
+use crate::quotas::Quota;
+
+// the version of the snapshot file format
+const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    IoError(std::io::Error),
+    SerdeError(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl Error for SnapshotError {}
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::IoError(e) => write!(f, "IoError: {}", e),
+            Self::SerdeError(e) => write!(f, "SerdeError: {}", e),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "UnsupportedVersion: {}, expected {}",
+                v, SNAPSHOT_VERSION
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::SerdeError(err)
+    }
+}
+
+// QuotaSnapshot is a quota as scanned. The usage and utilization are only set when the
+// utilization could be evaluated, otherwise the error is recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotaSnapshot {
+    pub arn: String,
+    pub account_id: String,
+    pub region: String,
+    pub service_code: String,
+    pub quota_code: String,
+    pub name: String,
+    pub applied_value: Option<f64>,
+    pub usage: Option<f64>,
+    pub percentage: Option<u8>,
+    pub source: Option<String>,
+    pub error: Option<String>,
+    // the runbook_url, account_alias and environment of the quota, when set
+    #[serde(flatten)]
+    pub metadata: QuotaMetadata,
+}
+
+impl QuotaSnapshot {
+    pub async fn new(quota: &dyn Quota, metadata: &Metadata) -> Self {
+        let utilization = quota.utilization().await;
+        let metadata = metadata.quota(
+            quota.quota_code().await,
+            quota.service_code().await,
+            quota.account_id().await,
+            quota.region().await,
+        );
+
+        Self {
+            arn: quota.arn().await.to_string(),
+            account_id: quota.account_id().await.to_string(),
+            region: quota.region().await.to_string(),
+            service_code: quota.service_code().await.to_string(),
+            quota_code: quota.quota_code().await.to_string(),
+            name: quota.name().await.to_string(),
+            applied_value: match quota.applied_value().await {
+                Some(applied_value) => Some(applied_value),
+                None => utilization.as_ref().ok().and_then(|u| u.limit),
+            },
+            usage: utilization.as_ref().ok().and_then(|u| u.usage),
+            percentage: utilization.as_ref().ok().map(|u| u.percentage),
+            source: utilization.as_ref().ok().map(|u| u.source.to_string()),
+            error: utilization.err().map(|e| e.to_string()),
+            metadata,
+        }
+    }
+
+    // key identifies the quota across snapshots
+    fn key(&self) -> (&str, &str, &str) {
+        (&self.account_id, &self.region, &self.quota_code)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    // unix timestamp, in seconds, of when the scan completed
+    pub created_at: i64,
+    pub regions: Vec<String>,
+    pub quotas: Vec<QuotaSnapshot>,
+}
+
+impl Snapshot {
+    pub fn new(regions: &[String], mut quotas: Vec<QuotaSnapshot>) -> Self {
+        quotas.sort_by(|a, b| a.key().cmp(&b.key()));
+
+        Self {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now().timestamp(),
+            regions: regions.to_vec(),
+            quotas,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let contents = std::fs::read_to_string(path)?;
+        let snapshot = serde_json::from_str::<Self>(&contents)?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+
+        Ok(snapshot)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Diff is the change between two snapshots
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Diff {
+    // quotas whose applied value changed, as (before, after)
+    pub applied_value: Vec<(QuotaSnapshot, QuotaSnapshot)>,
+    pub appeared: Vec<QuotaSnapshot>,
+    pub disappeared: Vec<QuotaSnapshot>,
+    // quotas whose utilization changed by at least the threshold, as (before, after)
+    pub utilization: Vec<(QuotaSnapshot, QuotaSnapshot)>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.applied_value.is_empty()
+            && self.appeared.is_empty()
+            && self.disappeared.is_empty()
+            && self.utilization.is_empty()
+    }
+}
+
+// diff compares two snapshots. Utilization changes are only reported when the percentage moved
+// by at least utilization_delta points, and applied values only when known in both snapshots.
+pub fn diff(before: &Snapshot, after: &Snapshot, utilization_delta: u8) -> Diff {
+    let before_quotas = before
+        .quotas
+        .iter()
+        .map(|q| (q.key(), q))
+        .collect::<BTreeMap<_, _>>();
+    let after_quotas = after
+        .quotas
+        .iter()
+        .map(|q| (q.key(), q))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut diff = Diff::default();
+
+    for (key, old) in &before_quotas {
+        let Some(new) = after_quotas.get(key) else {
+            diff.disappeared.push((*old).clone());
+            continue;
+        };
+
+        if let (Some(old_value), Some(new_value)) = (old.applied_value, new.applied_value) {
+            if old_value != new_value {
+                diff.applied_value.push(((*old).clone(), (*new).clone()));
+            }
+        }
+
+        if let (Some(old_percentage), Some(new_percentage)) = (old.percentage, new.percentage) {
+            if old_percentage.abs_diff(new_percentage) >= utilization_delta.max(1) {
+                diff.utilization.push(((*old).clone(), (*new).clone()));
+            }
+        }
+    }
+
+    diff.appeared = after_quotas
+        .iter()
+        .filter(|(key, _)| !before_quotas.contains_key(*key))
+        .map(|(_, quota)| (*quota).clone())
+        .collect();
+
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn quota(
+        quota_code: &str,
+        applied_value: Option<f64>,
+        percentage: Option<u8>,
+    ) -> QuotaSnapshot {
+        QuotaSnapshot {
+            arn: format!(
+                "arn:aws:servicequotas:us-east-1:123456789012:ec2/{}",
+                quota_code
+            ),
+            account_id: "123456789012".to_string(),
+            region: "us-east-1".to_string(),
+            service_code: "ec2".to_string(),
+            quota_code: quota_code.to_string(),
+            name: quota_code.to_string(),
+            applied_value,
+            usage: None,
+            percentage,
+            source: None,
+            error: None,
+            metadata: QuotaMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let regions = vec!["us-east-1".to_string()];
+        let before = Snapshot::new(
+            &regions,
+            vec![
+                quota("L-1", Some(5.0), Some(80)),
+                quota("L-2", Some(10.0), Some(10)),
+                quota("L-3", Some(10.0), Some(10)),
+                quota("L-4", None, None),
+            ],
+        );
+        let after = Snapshot::new(
+            &regions,
+            vec![
+                quota("L-1", Some(10.0), Some(40)),
+                quota("L-2", Some(10.0), Some(15)),
+                quota("L-4", Some(10.0), Some(50)),
+                quota("L-5", Some(10.0), Some(10)),
+            ],
+        );
+
+        let diff = diff(&before, &after, 10);
+
+        let codes = |quotas: &[QuotaSnapshot]| {
+            quotas
+                .iter()
+                .map(|q| q.quota_code.clone())
+                .collect::<Vec<_>>()
+        };
+        let changed = |changes: &[(QuotaSnapshot, QuotaSnapshot)]| {
+            changes
+                .iter()
+                .map(|(_, q)| q.quota_code.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(changed(&diff.applied_value), vec!["L-1"]);
+        assert_eq!(changed(&diff.utilization), vec!["L-1"]);
+        assert_eq!(codes(&diff.appeared), vec!["L-5"]);
+        assert_eq!(codes(&diff.disappeared), vec!["L-3"]);
+        assert!(!diff.is_empty());
+        assert!(super::diff(&before, &before, 10).is_empty());
+    }
+
+    #[test]
+    fn test_write_and_load() {
+        let path = std::env::temp_dir().join(format!(
+            "service-quotas-test-snapshot-{}.json",
+            std::process::id()
+        ));
+        let snapshot = Snapshot::new(
+            &["us-east-1".to_string()],
+            vec![quota("L-1", Some(5.0), Some(80))],
+        );
+
+        snapshot.write(&path).unwrap();
+        assert_eq!(Snapshot::load(&path).unwrap(), snapshot);
+
+        std::fs::write(
+            &path,
+            r#"{"version":0,"created_at":0,"regions":[],"quotas":[]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(SnapshotError::UnsupportedVersion(0))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
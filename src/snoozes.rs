@@ -0,0 +1,237 @@
+// Snoozes suppress the notifications for the quotas they match until they expire. Each snooze
+// records who owns it and why, so that a forgotten snooze is reported rather than hiding a
+// breach forever.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::Path;
+
+use crate::config::Matcher;
+use crate::quotas::Quota;
+use crate::util;
+
+#[derive(Debug)]
+pub enum SnoozeError {
+    IoError(std::io::Error),
+    ParseError(toml::de::Error),
+    InvalidExpiry(String),
+}
+
+impl Error for SnoozeError {}
+impl Display for SnoozeError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::IoError(e) => write!(f, "IoError: {}", e),
+            Self::ParseError(e) => write!(f, "ParseError: {}", e),
+            Self::InvalidExpiry(e) => write!(f, "InvalidExpiry: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnoozeError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+impl From<toml::de::Error> for SnoozeError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::ParseError(err)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SnoozeFile {
+    #[serde(default)]
+    snoozes: Vec<SnoozeEntry>,
+}
+
+// SnoozeEntry is a snooze as written in the file, the expiry is either a TOML datetime or an
+// RFC 3339 string
+#[derive(Debug, Deserialize)]
+struct SnoozeEntry {
+    #[serde(flatten)]
+    matcher: Matcher,
+    expires: toml::Value,
+    owner: String,
+    reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snooze {
+    pub matcher: Matcher,
+    pub expires: DateTime<Utc>,
+    pub owner: String,
+    pub reason: String,
+}
+
+impl TryFrom<SnoozeEntry> for Snooze {
+    type Error = SnoozeError;
+
+    fn try_from(entry: SnoozeEntry) -> Result<Self, Self::Error> {
+        let expires = util::toml_datetime(&entry.expires)
+            .ok_or_else(|| SnoozeError::InvalidExpiry(entry.expires.to_string()))?;
+
+        let expires = DateTime::parse_from_rfc3339(&expires)
+            .map_err(|err| SnoozeError::InvalidExpiry(format!("{}: {}", expires, err)))?;
+
+        Ok(Self {
+            matcher: entry.matcher,
+            expires: expires.with_timezone(&Utc),
+            owner: entry.owner,
+            reason: entry.reason,
+        })
+    }
+}
+
+impl Snooze {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires <= now
+    }
+}
+
+// Snoozes is the snoozes of the file, evaluated at the time the run started so that every quota
+// of a run sees the same snoozes
+#[derive(Debug, Clone)]
+pub struct Snoozes {
+    pub snoozes: Vec<Snooze>,
+    now: DateTime<Utc>,
+}
+
+impl Default for Snoozes {
+    fn default() -> Self {
+        Self {
+            snoozes: Vec::new(),
+            now: Utc::now(),
+        }
+    }
+}
+
+impl Snoozes {
+    pub fn load(path: impl AsRef<Path>, now: DateTime<Utc>) -> Result<Self, SnoozeError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::parse(&contents, now)
+    }
+
+    pub fn parse(contents: &str, now: DateTime<Utc>) -> Result<Self, SnoozeError> {
+        let file = toml::from_str::<SnoozeFile>(contents)?;
+        let snoozes = file
+            .snoozes
+            .into_iter()
+            .map(Snooze::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { snoozes, now })
+    }
+
+    // find is the index of the first snooze that has not expired and matches the quota
+    pub async fn find(&self, quota: &dyn Quota) -> Option<usize> {
+        for (index, snooze) in self.snoozes.iter().enumerate() {
+            if !snooze.is_expired(self.now) && snooze.matcher.matches(quota).await {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    pub fn is_expired(&self, snooze: &Snooze) -> bool {
+        snooze.is_expired(self.now)
+    }
+
+    // ignore adds a snooze that never expires for each quota code, for the deprecated --ignore
+    // option
+    pub fn ignore(&mut self, quota_codes: &[String]) {
+        for quota_code in quota_codes {
+            self.snoozes.push(Snooze {
+                matcher: Matcher {
+                    quota_code: Some(quota_code.clone()),
+                    ..Default::default()
+                },
+                expires: DateTime::<Utc>::MAX_UTC,
+                owner: "--ignore".to_string(),
+                reason: "ignored with the deprecated --ignore option".to_string(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let now = DateTime::parse_from_rfc3339("2026-10-18T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let snoozes = Snoozes::parse(
+            r#"
+            [[snoozes]]
+            quota_code = "L-0263D0A3"
+            region = "eu-west-1"
+            expires = 2026-11-01T00:00:00Z
+            owner = "platform"
+            reason = "increase requested"
+
+            [[snoozes]]
+            service_code = "lambda"
+            expires = "2026-10-01T00:00:00+02:00"
+            owner = "serverless"
+            reason = "migration"
+            "#,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(snoozes.snoozes.len(), 2);
+        assert_eq!(
+            snoozes.snoozes[0].matcher,
+            Matcher {
+                quota_code: Some("L-0263D0A3".to_string()),
+                region: Some("eu-west-1".to_string()),
+                ..Default::default()
+            }
+        );
+        assert!(!snoozes.is_expired(&snoozes.snoozes[0]));
+        assert!(snoozes.is_expired(&snoozes.snoozes[1]));
+        assert_eq!(
+            snoozes.snoozes[1].expires.to_rfc3339(),
+            "2026-09-30T22:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_ignore() {
+        let mut snoozes = Snoozes::default();
+        snoozes.ignore(&["L-0263D0A3".to_string()]);
+
+        assert_eq!(snoozes.snoozes.len(), 1);
+        assert_eq!(
+            snoozes.snoozes[0].matcher,
+            Matcher {
+                quota_code: Some("L-0263D0A3".to_string()),
+                ..Default::default()
+            }
+        );
+        assert!(!snoozes.is_expired(&snoozes.snoozes[0]));
+    }
+
+    #[test]
+    fn test_parse_invalid_expiry() {
+        let result = Snoozes::parse(
+            r#"
+            [[snoozes]]
+            quota_code = "L-0263D0A3"
+            expires = "next week"
+            owner = "platform"
+            reason = "increase requested"
+            "#,
+            Utc::now(),
+        );
+
+        assert!(matches!(result, Err(SnoozeError::InvalidExpiry(_))));
+    }
+}
@@ -0,0 +1,441 @@
+// Thresholds decide whether the utilization of a quota is a breach, and how severe it is.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::config::ThresholdRule;
+use crate::quotas::{Quota, Utilization};
+
+#[derive(Debug)]
+pub enum ThresholdError {
+    InvalidThreshold(String),
+}
+
+impl Error for ThresholdError {}
+impl Display for ThresholdError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::InvalidThreshold(e) => write!(f, "InvalidThreshold: {}", e),
+        }
+    }
+}
+
+// Severity is the tier of a breach, in increasing order of severity. The names match the
+// severities of PagerDuty events. Breaches are never info, it is the severity alerts are sent at
+// during maintenance windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Info => write!(f, "info"),
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+            Self::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+// Threshold is breached when utilization is at or over the percentage, or when fewer than
+// headroom units of the quota remain. A breach escalates to error and critical when utilization
+// is at or over those percentages, which must be at or over the percentage, in that order.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    pub percentage: u8,
+    pub headroom: Option<f64>,
+    pub error: Option<u8>,
+    pub critical: Option<u8>,
+    // an open alert is only resolved once utilization is below this percentage, so that a quota
+    // oscillating around the threshold does not flap
+    pub resolve: Option<u8>,
+    // a breach only triggers an alert once it has been breaching for this many consecutive
+    // evaluations, and for this many minutes
+    pub sustained_evaluations: Option<u32>,
+    pub sustained_minutes: Option<u32>,
+}
+
+impl Threshold {
+    // validate checks that the tiers are in order, so that an error or critical percentage
+    // below the percentage does not go unnoticed
+    fn validate(&self) -> Result<(), String> {
+        let tiers = [
+            ("threshold", Some(self.percentage)),
+            ("error threshold", self.error),
+            ("critical threshold", self.critical),
+        ];
+        let tiers = tiers
+            .iter()
+            .filter_map(|(name, percentage)| percentage.map(|p| (name, p)))
+            .collect::<Vec<_>>();
+
+        for pair in tiers.windows(2) {
+            let ((lower, lower_percentage), (higher, higher_percentage)) = (pair[0], pair[1]);
+            if higher_percentage < lower_percentage {
+                return Err(format!(
+                    "the {} of {}% is below the {} of {}%",
+                    higher, higher_percentage, lower, lower_percentage
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn breached(&self, utilization: &Utilization) -> bool {
+        self.severity(utilization).is_some()
+    }
+
+    // holds is true when an open alert should stay open although the quota is no longer
+    // breaching, because utilization has not dropped below the resolve threshold
+    pub fn holds(&self, utilization: &Utilization) -> bool {
+        match self.resolve {
+            Some(resolve) => utilization.percentage >= resolve,
+            None => false,
+        }
+    }
+
+    // sustained is true when a breach has lasted long enough to trigger an alert, given the
+    // number of consecutive evaluations it was breaching in, and for how many seconds
+    pub fn sustained(&self, evaluations: u32, seconds: i64) -> bool {
+        let evaluations_met = match self.sustained_evaluations {
+            Some(sustained) => evaluations >= sustained,
+            None => true,
+        };
+        let minutes_met = match self.sustained_minutes {
+            Some(sustained) => seconds >= i64::from(sustained) * 60,
+            None => true,
+        };
+
+        evaluations_met && minutes_met
+    }
+
+    // severity is the highest tier the utilization is at or over, or None when not breached.
+    // Only a breach of the percentage or headroom escalates to error and critical.
+    pub fn severity(&self, utilization: &Utilization) -> Option<Severity> {
+        let at_or_over = |percentage: Option<u8>| match percentage {
+            Some(percentage) => utilization.percentage >= percentage,
+            None => false,
+        };
+
+        let breached = utilization.percentage >= self.percentage
+            || matches!(
+                (self.headroom, utilization.headroom()),
+                (Some(threshold), Some(headroom)) if headroom < threshold
+            );
+        if !breached {
+            return None;
+        }
+
+        if at_or_over(self.critical) {
+            return Some(Severity::Critical);
+        }
+
+        if at_or_over(self.error) {
+            return Some(Severity::Error);
+        }
+
+        Some(Severity::Warning)
+    }
+}
+
+// Thresholds resolves the threshold for each quota, from the defaults and the rules of the
+// config file. Rules are applied in order, so later rules take precedence.
+#[derive(Debug, Clone)]
+pub struct Thresholds {
+    default: Threshold,
+    rules: Vec<ThresholdRule>,
+}
+
+impl Thresholds {
+    // new checks the tiers of the default threshold, and of each rule applied to it
+    pub fn new(default: Threshold, rules: &[ThresholdRule]) -> Result<Self, ThresholdError> {
+        default
+            .validate()
+            .map_err(ThresholdError::InvalidThreshold)?;
+        for rule in rules {
+            apply(default, rule).validate().map_err(|err| {
+                ThresholdError::InvalidThreshold(format!("{} for {}", err, rule.matcher))
+            })?;
+        }
+
+        Ok(Self {
+            default,
+            rules: rules.to_vec(),
+        })
+    }
+
+    pub async fn threshold(&self, quota: &dyn Quota) -> Threshold {
+        let mut threshold = self.default;
+
+        for rule in &self.rules {
+            if rule.matcher.matches(quota).await {
+                threshold = apply(threshold, rule);
+            }
+        }
+
+        threshold
+    }
+
+    // breached is true when the utilization of the quota is known, and breaches its threshold
+    pub async fn breached(&self, quota: &dyn Quota) -> bool {
+        match quota.utilization().await {
+            Ok(utilization) => self.threshold(quota).await.breached(&utilization),
+            Err(_) => false,
+        }
+    }
+}
+
+fn apply(threshold: Threshold, rule: &ThresholdRule) -> Threshold {
+    Threshold {
+        percentage: rule.percentage.unwrap_or(threshold.percentage),
+        headroom: rule.headroom.or(threshold.headroom),
+        error: rule.error.or(threshold.error),
+        critical: rule.critical.or(threshold.critical),
+        resolve: rule.resolve.or(threshold.resolve),
+        sustained_evaluations: rule
+            .sustained_evaluations
+            .or(threshold.sustained_evaluations),
+        sustained_minutes: rule.sustained_minutes.or(threshold.sustained_minutes),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Matcher;
+    use crate::quotas::Source;
+
+    fn utilization(usage: f64, limit: f64) -> Utilization {
+        Utilization {
+            percentage: (usage / limit * 100.0) as u8,
+            source: Source::Api,
+            usage: Some(usage),
+            limit: Some(limit),
+        }
+    }
+
+    #[test]
+    fn test_breached_percentage() {
+        let threshold = Threshold {
+            percentage: 75,
+            headroom: None,
+            ..Default::default()
+        };
+
+        assert!(!threshold.breached(&utilization(74.0, 100.0)));
+        assert!(threshold.breached(&utilization(75.0, 100.0)));
+    }
+
+    #[test]
+    fn test_breached_headroom() {
+        let threshold = Threshold {
+            percentage: 100,
+            headroom: Some(2.0),
+            ..Default::default()
+        };
+
+        assert!(!threshold.breached(&utilization(3.0, 5.0)));
+        assert!(threshold.breached(&utilization(4.0, 5.0)));
+        assert!(!threshold.breached(&utilization(900.0, 1000.0)));
+    }
+
+    #[test]
+    fn test_breached_headroom_unknown_usage() {
+        let threshold = Threshold {
+            percentage: 100,
+            headroom: Some(2.0),
+            ..Default::default()
+        };
+        let utilization = Utilization {
+            usage: None,
+            ..utilization(4.0, 5.0)
+        };
+
+        assert!(!threshold.breached(&utilization));
+    }
+
+    #[test]
+    fn test_apply() {
+        let threshold = Threshold {
+            percentage: 75,
+            headroom: None,
+            error: Some(90),
+            ..Default::default()
+        };
+        let rule = ThresholdRule {
+            matcher: Matcher::default(),
+            percentage: None,
+            headroom: Some(1.0),
+            error: None,
+            critical: Some(95),
+            resolve: Some(70),
+            sustained_evaluations: None,
+            sustained_minutes: Some(15),
+        };
+
+        assert_eq!(
+            apply(threshold, &rule),
+            Threshold {
+                percentage: 75,
+                headroom: Some(1.0),
+                error: Some(90),
+                critical: Some(95),
+                resolve: Some(70),
+                sustained_evaluations: None,
+                sustained_minutes: Some(15),
+            }
+        );
+    }
+
+    #[test]
+    fn test_severity() {
+        let threshold = Threshold {
+            percentage: 75,
+            headroom: Some(2.0),
+            error: Some(90),
+            critical: Some(95),
+            ..Default::default()
+        };
+
+        assert_eq!(threshold.severity(&utilization(74.0, 100.0)), None);
+        assert_eq!(
+            threshold.severity(&utilization(75.0, 100.0)),
+            Some(Severity::Warning)
+        );
+        assert_eq!(
+            threshold.severity(&utilization(90.0, 100.0)),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            threshold.severity(&utilization(99.0, 100.0)),
+            Some(Severity::Critical)
+        );
+        assert_eq!(
+            threshold.severity(&utilization(4.0, 5.0)),
+            Some(Severity::Warning)
+        );
+        assert_eq!(threshold.severity(&utilization(9.0, 20.0)), None);
+        assert_eq!(threshold.severity(&utilization(19.0, 40.0)), None);
+        assert_eq!(
+            threshold.severity(&utilization(39.0, 40.0)),
+            Some(Severity::Critical)
+        );
+    }
+
+    #[test]
+    fn test_severity_below_threshold() {
+        // only a breach of the percentage escalates, even with a lower error tier
+        let threshold = Threshold {
+            percentage: 90,
+            error: Some(80),
+            ..Default::default()
+        };
+        assert_eq!(threshold.severity(&utilization(85.0, 100.0)), None);
+
+        // a breach of the headroom escalates with utilization
+        let threshold = Threshold {
+            percentage: 100,
+            headroom: Some(10.0),
+            error: Some(95),
+            ..Default::default()
+        };
+        assert_eq!(
+            threshold.severity(&utilization(96.0, 100.0)),
+            Some(Severity::Error)
+        );
+    }
+
+    #[test]
+    fn test_new_validates_order() {
+        let threshold = Threshold {
+            percentage: 75,
+            error: Some(90),
+            critical: Some(95),
+            ..Default::default()
+        };
+        let rule = |error: Option<u8>, critical: Option<u8>| ThresholdRule {
+            matcher: Matcher {
+                quota_code: Some("L-1216C47A".to_string()),
+                ..Default::default()
+            },
+            percentage: None,
+            headroom: None,
+            error,
+            critical,
+            resolve: None,
+            sustained_evaluations: None,
+            sustained_minutes: None,
+        };
+
+        assert!(Thresholds::new(threshold, &[rule(Some(80), None)]).is_ok());
+        assert!(Thresholds::new(Threshold::default(), &[]).is_ok());
+
+        let err = Thresholds::new(
+            Threshold {
+                error: Some(70),
+                ..threshold
+            },
+            &[],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "InvalidThreshold: the error threshold of 70% is below the threshold of 75%"
+        );
+
+        let err = Thresholds::new(
+            Threshold {
+                error: None,
+                critical: Some(60),
+                ..threshold
+            },
+            &[],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "InvalidThreshold: the critical threshold of 60% is below the threshold of 75%"
+        );
+
+        let err = Thresholds::new(threshold, &[rule(None, Some(85))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "InvalidThreshold: the critical threshold of 85% is below the error threshold of 90% for quota_code=L-1216C47A"
+        );
+    }
+
+    #[test]
+    fn test_holds() {
+        let threshold = Threshold {
+            percentage: 80,
+            resolve: Some(70),
+            ..Default::default()
+        };
+
+        assert!(threshold.holds(&utilization(75.0, 100.0)));
+        assert!(!threshold.holds(&utilization(69.0, 100.0)));
+        assert!(!Threshold::default().holds(&utilization(75.0, 100.0)));
+    }
+
+    #[test]
+    fn test_sustained() {
+        let threshold = Threshold {
+            sustained_evaluations: Some(3),
+            sustained_minutes: Some(10),
+            ..Default::default()
+        };
+
+        assert!(!threshold.sustained(2, 3600));
+        assert!(!threshold.sustained(3, 599));
+        assert!(threshold.sustained(3, 600));
+        assert!(Threshold::default().sustained(1, 0));
+    }
+}
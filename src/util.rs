@@ -5,7 +5,8 @@ use aws_config::{
     retry::{RetryConfig, RetryMode},
     SdkConfig,
 };
-use aws_sdk_cloudwatch::Region;
+use aws_sdk_cloudwatch::{types::SdkError, Region};
+use aws_smithy_types::retry::ProvideErrorKind;
 
 // aws_config_with_region loads aws configurations for a specific region for use with aws clients
 pub async fn aws_config_with_region(region: &str) -> (SdkConfig, RetryConfig) {
@@ -16,6 +17,17 @@ pub async fn aws_config_with_region(region: &str) -> (SdkConfig, RetryConfig) {
     )
 }
 
+// default_region is the region of the environment or profile, or us-east-1, for the APIs that
+// are not called per scanned region
+pub async fn default_region() -> String {
+    RegionProviderChain::default_provider()
+        .or_else(Region::new("us-east-1"))
+        .region()
+        .await
+        .map(|r| r.to_string())
+        .unwrap_or_else(|| "us-east-1".to_string())
+}
+
 fn retry_config() -> RetryConfig {
     RetryConfig::standard()
         .with_initial_backoff(Duration::new(2, 0))
@@ -23,6 +35,38 @@ fn retry_config() -> RetryConfig {
         .with_max_attempts(5)
 }
 
+// sdk_error_code returns the error code of an AWS service error, e.g. AccessDeniedException.
+// Errors that did not receive a response from the service have no error code.
+pub fn sdk_error_code<E: ProvideErrorKind, R>(err: &SdkError<E, R>) -> Option<&str> {
+    match err {
+        SdkError::ServiceError { err, .. } => err.code(),
+        _ => None,
+    }
+}
+
+// is_throttling_error_code is true for the error codes AWS APIs use when rate limiting requests
+pub fn is_throttling_error_code(code: Option<&str>) -> bool {
+    matches!(
+        code,
+        Some(
+            "Throttling"
+                | "ThrottlingException"
+                | "RequestLimitExceeded"
+                | "TooManyRequestsException"
+        )
+    )
+}
+
+// toml_datetime is the text of a TOML value written either as a TOML datetime or time, or as a
+// string, so that config files can use either form
+pub fn toml_datetime(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::Datetime(datetime) => Some(datetime.to_string()),
+        toml::Value::String(datetime) => Some(datetime.clone()),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;